@@ -0,0 +1,32 @@
+use crate::resp::ProtocolVersion;
+use bytes::Bytes;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// State that belongs to a single connection and lives as long as it does.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Client {
+    pub id: u64,
+    pub protocol: ProtocolVersion,
+    pub name: Option<Bytes>,
+    /// Set by SHUTDOWN: the connection is closed without a reply.
+    pub closing: bool,
+}
+
+impl Client {
+    pub fn new() -> Self {
+        Client {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            protocol: ProtocolVersion::RESP2,
+            name: None,
+            closing: false,
+        }
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,94 @@
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The time key expiry is measured against, as Unix time in milliseconds.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> u128;
+}
+
+/// Reads the wall clock once and then follows the monotonic clock, so a
+/// wall-clock jump does not expire keys early or late.
+#[derive(Debug)]
+pub struct SystemClock {
+    started_at: u128,
+    started: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock {
+            started_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis(),
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> u128 {
+        self.started_at + self.started.elapsed().as_millis()
+    }
+}
+
+/// A clock that only moves when told to, for tests.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(now: u64) -> Self {
+        ManualClock {
+            now: AtomicU64::new(now),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now
+            .fetch_add(duration.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn set(&self, now: u64) {
+        self.now.store(now, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u128 {
+        self.now.load(Ordering::Relaxed) as u128
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, ManualClock, SystemClock};
+    use std::time::Duration;
+
+    #[test]
+    fn it_should_follow_the_monotonic_clock() {
+        let clock = SystemClock::new();
+        let first = clock.now();
+        assert!(clock.now() >= first);
+    }
+
+    #[test]
+    fn it_should_only_move_a_manual_clock_when_told() {
+        let clock = ManualClock::new(1_000);
+        assert_eq!(clock.now(), 1_000);
+
+        clock.advance(Duration::from_millis(101));
+        assert_eq!(clock.now(), 1_101);
+
+        clock.set(5);
+        assert_eq!(clock.now(), 5);
+    }
+}
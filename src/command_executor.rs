@@ -1,255 +1,2102 @@
-use crate::resp::{BULK_STRING, CRLF, ERROR, INTEGER, NULL_STRING, RESP, SIMPLE_STRING};
-use crate::store::{SetOptions, Store};
-use std::sync::RwLock;
+use crate::client::Client;
+use crate::command_table::{self, Command, COMMAND_TABLE};
+use crate::config::{Config, PARAMETERS};
+use crate::glob::glob_match;
+use crate::resp::{parse_integer, ProtocolVersion, RESP};
+use crate::resp_encoder::RESPEncoder;
+use crate::server::{Server, Stats};
+use crate::store::{Expiration, SetCondition, SetOptions};
+use bytes::Bytes;
+use std::sync::PoisonError;
+
+pub const REDIS_VERSION: &str = "7.2.0";
+
+pub type CommandResult = Result<RESP, CommandError>;
+
+/// The type names TYPE replies with and SCAN ... TYPE accepts.
+const VALUE_TYPES: &[&str] = &["string", "list", "set", "zset", "hash", "stream"];
+
+/// Errors a command replies with. The first word of the message is the
+/// error code the client sees, e.g. `ERR` or `WRONGTYPE`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CommandError {
+    #[error("ERR unknown command '{0}', with args beginning with: {1}")]
+    UnknownCommand(String, String),
+    #[error("ERR unknown subcommand '{0}'. Try {1} HELP.")]
+    UnknownSubcommand(String, String),
+    #[error("ERR wrong number of arguments for '{0}' command")]
+    WrongNumberOfArguments(String),
+    #[error("ERR value is not an integer or out of range")]
+    NotAnInteger,
+    #[error("ERR syntax error")]
+    SyntaxError,
+    #[error("ERR invalid expire time in '{0}' command")]
+    InvalidExpireTime(String),
+    #[error("ERR no such key")]
+    NoSuchKey,
+    #[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+    WrongType,
+    #[error("NOPROTO unsupported protocol version")]
+    NoProto,
+    #[error("WRONGPASS invalid username-password pair or user is disabled.")]
+    WrongPass,
+    /// A lock was poisoned by a command that panicked.
+    #[error("ERR internal server error occurred")]
+    Internal,
+    #[error("ERR {0}")]
+    Generic(String),
+}
+
+impl<T> From<PoisonError<T>> for CommandError {
+    fn from(_: PoisonError<T>) -> Self {
+        CommandError::Internal
+    }
+}
+
+impl From<CommandError> for RESP {
+    fn from(error: CommandError) -> Self {
+        let message = sanitize_error_message(&error.to_string());
+        match message.split_once(' ') {
+            Some((code, message)) => RESP::new_error(code, message),
+            None => RESP::new_error("ERR", &message),
+        }
+    }
+}
+
+/// Replaces the line breaks of an error message, so client input echoed back
+/// in it cannot break the framing of the reply.
+pub fn sanitize_error_message(message: &str) -> String {
+    message.replace(['\r', '\n'], " ")
+}
 
-#[derive(Clone)]
 pub struct CommandExecutor<'a> {
     args: &'a Vec<RESP>,
-    store: &'a RwLock<Store>,
+    server: &'a Server,
+    client: &'a mut Client,
 }
 
 impl<'a> CommandExecutor<'a> {
-    pub fn new(args: &'a Vec<RESP>, store: &'a RwLock<Store>) -> Self {
-        CommandExecutor { args, store }
-    }
-
-    pub fn execute(&self) -> Vec<u8> {
-        let mut response_bytes: Vec<u8> = vec![];
-        if let Some(command) = self.command() {
-            match &*command {
-                "ping" => append_simple_string(&mut response_bytes, "PONG".to_string()),
-                "echo" => self.execute_echo_command(&mut response_bytes),
-                "set" => self.execute_set_command(&mut response_bytes),
-                "get" => self.execute_get_command(&mut response_bytes),
-                // TODO: implement the remaining commands
-                _ => append_error(
-                    &mut response_bytes,
-                    "ERR".to_string(),
-                    "unsupported command".to_string(),
-                ),
+    pub fn new(args: &'a Vec<RESP>, server: &'a Server, client: &'a mut Client) -> Self {
+        CommandExecutor {
+            args,
+            server,
+            client,
+        }
+    }
+
+    pub fn execute(&mut self) -> Vec<u8> {
+        Stats::increment(&self.server.stats.total_commands_processed);
+        let reply = self.dispatch().unwrap_or_else(RESP::from);
+        if self.client.closing {
+            return vec![];
+        }
+
+        // encode after running the command so HELLO replies in the new protocol
+        RESPEncoder::new(self.client.protocol).encode(&reply)
+    }
+
+    /// Looks the command up in the command table, checks its arity and runs
+    /// its handler, descending into subcommands for containers like CONFIG.
+    fn dispatch(&mut self) -> CommandResult {
+        let name = match self.command() {
+            Some(name) => name,
+            None => return Err(CommandError::Generic("command is not provided".to_string())),
+        };
+        let mut command = match command_table::lookup(&name) {
+            Some(command) => command,
+            None => return Err(unknown_command(self.args)),
+        };
+        if !command.has_valid_arity(self.args.len()) {
+            return Err(wrong_number_of_arguments(command.name));
+        }
+
+        if !command.subcommands.is_empty() && self.args.len() >= 2 {
+            let subcommand = self.args[1].string();
+            command = match command.subcommand(&subcommand) {
+                Some(command) => command,
+                None => {
+                    return Err(CommandError::UnknownSubcommand(
+                        truncate(self.args[1].bytes(), 128),
+                        name.to_uppercase(),
+                    ))
+                }
+            };
+            if !command.has_valid_arity(self.args.len()) {
+                return Err(wrong_number_of_arguments(command.name));
             }
-        } else {
-            append_error(
-                &mut response_bytes,
-                "ERR".to_string(),
-                "command is not provided".to_string(),
-            );
         }
 
-        response_bytes
+        match command.handler {
+            Some(handler) => handler(self),
+            None => Err(wrong_number_of_arguments(command.name)),
+        }
     }
 
     fn command(&self) -> Option<String> {
-        if self.args.len() == 0 {
+        if self.args.is_empty() {
             return None;
         }
 
         Some(self.args[0].string().to_lowercase())
     }
 
-    fn execute_echo_command(&self, mut bytes: &mut Vec<u8>) {
-        if self.args.len() <= 1 {
-            append_error(
-                &mut bytes,
-                "ERR".to_string(),
-                "wrong number of arguments for 'echo' command".to_string(),
-            );
-            return;
+    pub(crate) fn execute_ping_command(&self) -> CommandResult {
+        if self.args.len() > 2 {
+            return Err(wrong_number_of_arguments("ping"));
+        }
+
+        match self.args.get(1) {
+            Some(message) => Ok(RESP::new_bulk_string(message.bytes().clone())),
+            None => Ok(RESP::new_simple_string("PONG")),
+        }
+    }
+
+    pub(crate) fn execute_echo_command(&self) -> CommandResult {
+        Ok(RESP::new_bulk_string(self.args[1].bytes().clone()))
+    }
+
+    /// SET key value [NX | XX] [GET] [EX seconds | PX milliseconds |
+    /// EXAT unix-time-seconds | PXAT unix-time-milliseconds | KEEPTTL]
+    pub(crate) fn execute_set_command(&self) -> CommandResult {
+        let key = self.args[1].bytes().clone();
+        let value = self.args[2].bytes().clone();
+        let mut condition = SetCondition::Always;
+        let mut get = false;
+        let mut keep_ttl = false;
+        let mut expire: Option<(String, &RESP)> = None;
+        let mut index = 3;
+        while index < self.args.len() {
+            let option = self.args[index].string().to_lowercase();
+            let next = self.args.get(index + 1);
+            // repeating an option is fine, combining conflicting ones is not
+            let other_expire = matches!(&expire, Some((unit, _)) if *unit != option);
+            match (&*option, next) {
+                ("nx", _) if condition != SetCondition::IfExists => {
+                    condition = SetCondition::IfNotExists
+                }
+                ("xx", _) if condition != SetCondition::IfNotExists => {
+                    condition = SetCondition::IfExists
+                }
+                ("get", _) => get = true,
+                ("keepttl", _) if expire.is_none() => keep_ttl = true,
+                ("ex" | "px" | "exat" | "pxat", Some(next)) if !keep_ttl && !other_expire => {
+                    expire = Some((option, next));
+                    index += 1;
+                }
+                _ => return Err(CommandError::SyntaxError),
+            }
+            index += 1;
+        }
+
+        let mut set_options = SetOptions::new();
+        set_options.set_condition(condition);
+        if keep_ttl {
+            set_options.set_expiration(Expiration::KeepTtl);
+        }
+        if let Some((unit, value)) = expire {
+            set_options.set_expiration(Expiration::At(parse_expiration(
+                &unit,
+                value,
+                "set",
+                self.server.clock.now(),
+            )?));
+        }
+
+        let outcome = self.server.store.write()?.set(key, value, &set_options);
+        match (get, outcome.written) {
+            (true, _) => Ok(outcome
+                .previous
+                .map_or_else(RESP::new_null, RESP::new_bulk_string)),
+            (false, true) => Ok(RESP::new_simple_string("OK")),
+            (false, false) => Ok(RESP::new_null()),
+        }
+    }
+
+    pub(crate) fn execute_get_command(&self) -> CommandResult {
+        let value = self.server.store.write()?.get(self.args[1].bytes());
+        Ok(self.lookup_reply(value))
+    }
+
+    pub(crate) fn execute_getdel_command(&self) -> CommandResult {
+        let value = self.server.store.write()?.remove(self.args[1].bytes());
+        Ok(self.lookup_reply(value))
+    }
+
+    /// GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+    /// PXAT unix-time-milliseconds | PERSIST]
+    pub(crate) fn execute_getex_command(&self) -> CommandResult {
+        let mut persist = false;
+        let mut expire: Option<(String, &RESP)> = None;
+        let mut index = 2;
+        while index < self.args.len() {
+            let option = self.args[index].string().to_lowercase();
+            let next = self.args.get(index + 1);
+            let other_expire = matches!(&expire, Some((unit, _)) if *unit != option);
+            match (&*option, next) {
+                ("persist", _) if expire.is_none() => persist = true,
+                ("ex" | "px" | "exat" | "pxat", Some(next)) if !persist && !other_expire => {
+                    expire = Some((option, next));
+                    index += 1;
+                }
+                _ => return Err(CommandError::SyntaxError),
+            }
+            index += 1;
         }
+        let expired_at = match expire {
+            Some((unit, value)) => Some(parse_expiration(
+                &unit,
+                value,
+                "getex",
+                self.server.clock.now(),
+            )?),
+            None => None,
+        };
+
+        let key = self.args[1].bytes();
+        let mut store = self.server.store.write()?;
+        let value = store.get(key);
+        if value.is_some() && (persist || expired_at.is_some()) {
+            store.set_expired_at(key, expired_at);
+        }
+        Ok(self.lookup_reply(value))
+    }
+
+    pub(crate) fn execute_getset_command(&self) -> CommandResult {
+        let key = self.args[1].bytes().clone();
+        let value = self.args[2].bytes().clone();
+        let outcome = self
+            .server
+            .store
+            .write()?
+            .set(key, value, &SetOptions::new());
+        Ok(self.lookup_reply(outcome.previous))
+    }
+
+    pub(crate) fn execute_setnx_command(&self) -> CommandResult {
+        let key = self.args[1].bytes().clone();
+        let value = self.args[2].bytes().clone();
+        let mut options = SetOptions::new();
+        options.set_condition(SetCondition::IfNotExists);
+        let outcome = self.server.store.write()?.set(key, value, &options);
+        Ok(RESP::new_integer(outcome.written as i64))
+    }
+
+    pub(crate) fn execute_setex_command(&self) -> CommandResult {
+        self.set_with_expiration("ex", "setex")
+    }
+
+    pub(crate) fn execute_psetex_command(&self) -> CommandResult {
+        self.set_with_expiration("px", "psetex")
+    }
+
+    /// SETEX and PSETEX: `key ttl value`, where `unit` is how SET spells the TTL.
+    fn set_with_expiration(&self, unit: &str, command: &str) -> CommandResult {
+        let key = self.args[1].bytes().clone();
+        let value = self.args[3].bytes().clone();
+        let mut options = SetOptions::new();
+        options.set_expiration(Expiration::At(parse_expiration(
+            unit,
+            &self.args[2],
+            command,
+            self.server.clock.now(),
+        )?));
+        self.server.store.write()?.set(key, value, &options);
+        Ok(RESP::new_simple_string("OK"))
+    }
+
+    /// Counts a key lookup in the keyspace stats and replies with the value.
+    fn lookup_reply(&self, value: Option<Bytes>) -> RESP {
+        match value {
+            Some(value) => {
+                Stats::increment(&self.server.stats.keyspace_hits);
+                RESP::new_bulk_string(value)
+            }
+            None => {
+                Stats::increment(&self.server.stats.keyspace_misses);
+                RESP::new_null()
+            }
+        }
+    }
 
-        let value = self.args[1].string();
-        append_bulk_string(&mut bytes, value)
+    pub(crate) fn execute_incr_command(&self) -> CommandResult {
+        self.increment_by(1)
     }
 
-    fn execute_set_command(&self, mut bytes: &mut Vec<u8>) {
-        if self.args.len() <= 2 {
-            append_error(
-                &mut bytes,
-                "ERR".to_string(),
-                "wrong number of arguments for 'set' command".to_string(),
+    pub(crate) fn execute_decr_command(&self) -> CommandResult {
+        self.increment_by(-1)
+    }
+
+    pub(crate) fn execute_incrby_command(&self) -> CommandResult {
+        let increment = self.args[2].integer().ok_or(CommandError::NotAnInteger)?;
+        self.increment_by(increment)
+    }
+
+    pub(crate) fn execute_decrby_command(&self) -> CommandResult {
+        let decrement = self.args[2].integer().ok_or(CommandError::NotAnInteger)?;
+        if decrement == i64::MIN {
+            return Err(CommandError::Generic(
+                "decrement would overflow".to_string(),
+            ));
+        }
+        self.increment_by(-decrement)
+    }
+
+    /// Reads, adds and writes back under one write guard, so concurrent
+    /// increments are never lost. The key keeps its TTL.
+    fn increment_by(&self, increment: i64) -> CommandResult {
+        let key = self.args[1].bytes();
+        let mut store = self.server.store.write()?;
+        let current = match store.get(key) {
+            Some(value) => parse_integer(&value).ok_or(CommandError::NotAnInteger)?,
+            None => 0,
+        };
+        let value = current.checked_add(increment).ok_or_else(|| {
+            CommandError::Generic("increment or decrement would overflow".to_string())
+        })?;
+
+        store.set(key.clone(), value.to_string().into(), &keep_ttl());
+        Ok(RESP::new_integer(value))
+    }
+
+    pub(crate) fn execute_incrbyfloat_command(&self) -> CommandResult {
+        let not_a_float = || CommandError::Generic("value is not a valid float".to_string());
+        let increment = parse_float(self.args[2].bytes()).ok_or_else(not_a_float)?;
+        let key = self.args[1].bytes();
+        let mut store = self.server.store.write()?;
+        let current = match store.get(key) {
+            Some(value) => parse_float(&value).ok_or_else(not_a_float)?,
+            None => 0.0,
+        };
+        let value = current + increment;
+        if !value.is_finite() {
+            return Err(CommandError::Generic(
+                "increment would produce NaN or Infinity".to_string(),
+            ));
+        }
+
+        let value = format_float(value);
+        store.set(key.clone(), value.clone().into(), &keep_ttl());
+        Ok(RESP::new_bulk_string(value))
+    }
+
+    pub(crate) fn execute_append_command(&self) -> CommandResult {
+        let key = self.args[1].bytes();
+        let suffix = self.args[2].bytes();
+        let max_len = self.max_string_len()?;
+        let mut store = self.server.store.write()?;
+        let length = store.get_mut(key).map_or(0, |value| value.len());
+        check_string_length(length as u64 + suffix.len() as u64, max_len)?;
+
+        let value = store.get_or_insert_mut(key);
+        value.extend_from_slice(suffix);
+        Ok(RESP::new_integer(value.len() as i64))
+    }
+
+    pub(crate) fn execute_strlen_command(&self) -> CommandResult {
+        let mut store = self.server.store.write()?;
+        let length = store
+            .get(self.args[1].bytes())
+            .map_or(0, |value| value.len());
+        Ok(RESP::new_integer(length as i64))
+    }
+
+    /// GETRANGE and SUBSTR. Negative offsets count from the end and the range
+    /// is clamped to the string, so it never fails on a short value.
+    pub(crate) fn execute_getrange_command(&self) -> CommandResult {
+        let start = self.args[2].integer().ok_or(CommandError::NotAnInteger)?;
+        let end = self.args[3].integer().ok_or(CommandError::NotAnInteger)?;
+        let value = self
+            .server
+            .store
+            .write()?
+            .get(self.args[1].bytes())
+            .unwrap_or_default();
+        if start < 0 && end < 0 && start > end {
+            return Ok(RESP::new_bulk_string(""));
+        }
+
+        let length = value.len() as i64;
+        let start = if start < 0 { length + start } else { start }.max(0);
+        let end = if end < 0 { length + end } else { end }.clamp(0, (length - 1).max(0));
+        if start > end || length == 0 {
+            return Ok(RESP::new_bulk_string(""));
+        }
+        Ok(RESP::new_bulk_string(
+            value.slice(start as usize..=end as usize),
+        ))
+    }
+
+    /// Overwrites part of the value at `offset`, padding with zero bytes when
+    /// the value is shorter than the offset.
+    pub(crate) fn execute_setrange_command(&self) -> CommandResult {
+        let key = self.args[1].bytes();
+        let offset = self.args[2].integer().ok_or(CommandError::NotAnInteger)?;
+        if offset < 0 {
+            return Err(CommandError::Generic("offset is out of range".to_string()));
+        }
+        let patch = self.args[3].bytes();
+        let max_len = self.max_string_len()?;
+        let mut store = self.server.store.write()?;
+        if patch.is_empty() {
+            // nothing to write, and a missing key is not created
+            let length = store.get_mut(key).map_or(0, |value| value.len());
+            return Ok(RESP::new_integer(length as i64));
+        }
+        check_string_length(offset as u64 + patch.len() as u64, max_len)?;
+
+        let offset = offset as usize;
+        let value = store.get_or_insert_mut(key);
+        if value.len() < offset + patch.len() {
+            value.resize(offset + patch.len(), 0);
+        }
+        value[offset..offset + patch.len()].copy_from_slice(patch);
+        Ok(RESP::new_integer(value.len() as i64))
+    }
+
+    /// Replies with the longest common subsequence of two strings, its length
+    /// with LEN, or the runs it is made of with IDX.
+    pub(crate) fn execute_lcs_command(&self) -> CommandResult {
+        let mut get_len = false;
+        let mut get_idx = false;
+        let mut with_match_len = false;
+        let mut min_match_len = 0;
+        let mut index = 3;
+        while index < self.args.len() {
+            let option = self.args[index].string().to_lowercase();
+            match (&*option, self.args.get(index + 1)) {
+                ("len", _) => get_len = true,
+                ("idx", _) => get_idx = true,
+                ("withmatchlen", _) => with_match_len = true,
+                ("minmatchlen", Some(next)) => {
+                    min_match_len = next.integer().ok_or(CommandError::NotAnInteger)?.max(0);
+                    index += 1;
+                }
+                _ => return Err(CommandError::SyntaxError),
+            }
+            index += 1;
+        }
+        if get_len && get_idx {
+            return Err(CommandError::Generic(
+                "If you want both the length and indexes, please just use IDX.".to_string(),
+            ));
+        }
+
+        // missing keys compare as empty strings
+        let (a, b) = {
+            let mut store = self.server.store.write()?;
+            let a = store.get(self.args[1].bytes()).unwrap_or_default();
+            let b = store.get(self.args[2].bytes()).unwrap_or_default();
+            (a, b)
+        };
+        let table_size = (a.len() as u64 + 1)
+            .checked_mul(b.len() as u64 + 1)
+            .and_then(|cells| cells.checked_mul(4));
+        if !matches!(table_size, Some(size) if size <= self.max_string_len()?) {
+            return Err(CommandError::Generic(
+                "Insufficient memory, transient memory for LCS exceeds proto-max-bulk-len"
+                    .to_string(),
+            ));
+        }
+
+        let (subsequence, runs) = longest_common_subsequence(&a, &b);
+        if get_len {
+            return Ok(RESP::new_integer(subsequence.len() as i64));
+        }
+        if !get_idx {
+            return Ok(RESP::new_bulk_string(subsequence));
+        }
+
+        let range = |(start, end): (usize, usize)| {
+            RESP::new_array(vec![
+                RESP::new_integer(start as i64),
+                RESP::new_integer(end as i64),
+            ])
+        };
+        let matches = runs
+            .into_iter()
+            .filter(|run| run.len() as i64 >= min_match_len)
+            .map(|run| {
+                let mut reply = vec![range(run.a), range(run.b)];
+                if with_match_len {
+                    reply.push(RESP::new_integer(run.len() as i64));
+                }
+                RESP::new_array(reply)
+            })
+            .collect();
+        Ok(RESP::new_map(vec![
+            (RESP::new_bulk_string("matches"), RESP::new_array(matches)),
+            (
+                RESP::new_bulk_string("len"),
+                RESP::new_integer(subsequence.len() as i64),
+            ),
+        ]))
+    }
+
+    /// The largest string a command may build, `proto-max-bulk-len`.
+    fn max_string_len(&self) -> Result<u64, CommandError> {
+        Ok(self.server.config.read()?.proto_max_bulk_len)
+    }
+
+    pub(crate) fn execute_mget_command(&self) -> CommandResult {
+        let mut store = self.server.store.write()?;
+        let values = self.args[1..]
+            .iter()
+            .map(|key| self.lookup_reply(store.get(key.bytes())))
+            .collect();
+        Ok(RESP::new_array(values))
+    }
+
+    pub(crate) fn execute_mset_command(&self) -> CommandResult {
+        let pairs = self.key_value_pairs("mset")?;
+        let mut store = self.server.store.write()?;
+        for pair in pairs {
+            store.set(
+                pair[0].bytes().clone(),
+                pair[1].bytes().clone(),
+                &SetOptions::new(),
             );
-            return;
         }
+        Ok(RESP::new_simple_string("OK"))
+    }
+
+    /// Sets every pair only if none of the keys exists.
+    pub(crate) fn execute_msetnx_command(&self) -> CommandResult {
+        let pairs = self.key_value_pairs("msetnx")?;
+        let mut store = self.server.store.write()?;
+        if pairs
+            .iter()
+            .any(|pair| store.get(pair[0].bytes()).is_some())
+        {
+            return Ok(RESP::new_integer(0));
+        }
+        for pair in pairs {
+            store.set(
+                pair[0].bytes().clone(),
+                pair[1].bytes().clone(),
+                &SetOptions::new(),
+            );
+        }
+        Ok(RESP::new_integer(1))
+    }
+
+    fn key_value_pairs(&self, command: &str) -> Result<Vec<&[RESP]>, CommandError> {
+        if self.args.len().is_multiple_of(2) {
+            return Err(wrong_number_of_arguments(command));
+        }
+        Ok(self.args[1..].chunks(2).collect())
+    }
+
+    /// Deletes the given keys and replies with how many existed. UNLINK
+    /// shares it, as there is nothing to free in the background.
+    pub(crate) fn execute_del_command(&self) -> CommandResult {
+        let mut store = self.server.store.write()?;
+        let deleted = self.args[1..]
+            .iter()
+            .filter(|key| store.remove(key.bytes()).is_some())
+            .count();
+        Ok(RESP::new_integer(deleted as i64))
+    }
+
+    /// Counts the given keys that exist, a key given twice counting twice.
+    /// TOUCH shares it, as there is no access time to update.
+    pub(crate) fn execute_exists_command(&self) -> CommandResult {
+        let mut store = self.server.store.write()?;
+        let existing = self.args[1..]
+            .iter()
+            .filter(|key| store.contains_key(key.bytes()))
+            .count();
+        Ok(RESP::new_integer(existing as i64))
+    }
+
+    pub(crate) fn execute_type_command(&self) -> CommandResult {
+        let mut store = self.server.store.write()?;
+        if store.contains_key(self.args[1].bytes()) {
+            Ok(RESP::new_simple_string("string"))
+        } else {
+            Ok(RESP::new_simple_string("none"))
+        }
+    }
+
+    pub(crate) fn execute_rename_command(&self) -> CommandResult {
+        let key = self.args[1].bytes();
+        let new_key = self.args[2].bytes().clone();
+        if !self.server.store.write()?.rename(key, new_key) {
+            return Err(CommandError::NoSuchKey);
+        }
+        Ok(RESP::new_simple_string("OK"))
+    }
+
+    pub(crate) fn execute_renamenx_command(&self) -> CommandResult {
+        let key = self.args[1].bytes();
+        let new_key = self.args[2].bytes();
+        let mut store = self.server.store.write()?;
+        if !store.contains_key(key) {
+            return Err(CommandError::NoSuchKey);
+        }
+        if store.contains_key(new_key) {
+            return Ok(RESP::new_integer(0));
+        }
+        store.rename(key, new_key.clone());
+        Ok(RESP::new_integer(1))
+    }
+
+    /// Copies a key with its TTL. There is only database 0, so `DB` can only
+    /// name the current one.
+    pub(crate) fn execute_copy_command(&self) -> CommandResult {
+        let mut replace = false;
+        let mut index = 3;
+        while index < self.args.len() {
+            let option = self.args[index].string().to_lowercase();
+            match (&*option, self.args.get(index + 1)) {
+                ("replace", _) => replace = true,
+                ("db", Some(next)) => {
+                    let db = next.integer().ok_or(CommandError::NotAnInteger)?;
+                    if db != 0 {
+                        return Err(CommandError::Generic(
+                            "DB index is out of range".to_string(),
+                        ));
+                    }
+                    index += 1;
+                }
+                _ => return Err(CommandError::SyntaxError),
+            }
+            index += 1;
+        }
+
+        let key = self.args[1].bytes();
+        let new_key = self.args[2].bytes();
+        if key == new_key {
+            return Err(CommandError::Generic(
+                "source and destination objects are the same".to_string(),
+            ));
+        }
+        let mut store = self.server.store.write()?;
+        if !store.contains_key(key) || (!replace && store.contains_key(new_key)) {
+            return Ok(RESP::new_integer(0));
+        }
+        store.copy(key, new_key.clone());
+        Ok(RESP::new_integer(1))
+    }
+
+    pub(crate) fn execute_expire_command(&self) -> CommandResult {
+        self.expire(1000, true, "expire")
+    }
+
+    pub(crate) fn execute_pexpire_command(&self) -> CommandResult {
+        self.expire(1, true, "pexpire")
+    }
+
+    pub(crate) fn execute_expireat_command(&self) -> CommandResult {
+        self.expire(1000, false, "expireat")
+    }
+
+    pub(crate) fn execute_pexpireat_command(&self) -> CommandResult {
+        self.expire(1, false, "pexpireat")
+    }
+
+    /// Sets the expiry to the time argument in units of `unit_ms`, relative
+    /// to now when `relative`. A time in the past deletes the key.
+    fn expire(&self, unit_ms: i64, relative: bool, command: &str) -> CommandResult {
+        let (mut nx, mut xx, mut gt, mut lt) = (false, false, false, false);
+        for arg in &self.args[3..] {
+            match &*arg.string().to_lowercase() {
+                "nx" => nx = true,
+                "xx" => xx = true,
+                "gt" => gt = true,
+                "lt" => lt = true,
+                _ => {
+                    return Err(CommandError::Generic(format!(
+                        "Unsupported option {}",
+                        arg.string()
+                    )))
+                }
+            }
+        }
+        if nx && (xx || gt || lt) {
+            return Err(CommandError::Generic(
+                "NX and XX, GT or LT options at the same time are not compatible".to_string(),
+            ));
+        }
+        if gt && lt {
+            return Err(CommandError::Generic(
+                "GT and LT options at the same time are not compatible".to_string(),
+            ));
+        }
+
+        let time = self.args[2].integer().ok_or(CommandError::NotAnInteger)?;
+        let invalid = || CommandError::InvalidExpireTime(command.to_string());
+        let mut expired_at = time.checked_mul(unit_ms).ok_or_else(invalid)? as i128;
+        let now = self.server.clock.now() as i128;
+        if relative {
+            expired_at = i64::try_from(expired_at + now).map_err(|_| invalid())? as i128;
+        }
+
+        let key = self.args[1].bytes();
+        let mut store = self.server.store.write()?;
+        let current = match store.expired_at(key) {
+            Some(current) => current.map(|current| current as i128),
+            None => return Ok(RESP::new_integer(0)),
+        };
+        // a key without a TTL counts as expiring at infinity for GT and LT
+        let rejected = nx && current.is_some()
+            || xx && current.is_none()
+            || gt && !matches!(current, Some(current) if expired_at > current)
+            || lt && matches!(current, Some(current) if expired_at >= current);
+        if rejected {
+            return Ok(RESP::new_integer(0));
+        }
+
+        if expired_at <= now {
+            store.remove(key);
+        } else {
+            store.set_expired_at(key, Some(expired_at as u128));
+        }
+        Ok(RESP::new_integer(1))
+    }
+
+    pub(crate) fn execute_ttl_command(&self) -> CommandResult {
+        self.ttl(false)
+    }
+
+    pub(crate) fn execute_pttl_command(&self) -> CommandResult {
+        self.ttl(true)
+    }
+
+    /// Replies with the time left to live, -1 for a key without a TTL and -2
+    /// for a missing key. Seconds are rounded to the nearest one.
+    fn ttl(&self, in_ms: bool) -> CommandResult {
+        let expired_at = self.server.store.write()?.expired_at(self.args[1].bytes());
+        let ttl = match expired_at {
+            Some(Some(expired_at)) => {
+                let ttl = expired_at.saturating_sub(self.server.clock.now()) as i64;
+                if in_ms {
+                    ttl
+                } else {
+                    (ttl + 500) / 1000
+                }
+            }
+            Some(None) => -1,
+            None => -2,
+        };
+        Ok(RESP::new_integer(ttl))
+    }
+
+    pub(crate) fn execute_expiretime_command(&self) -> CommandResult {
+        self.expire_time(false)
+    }
+
+    pub(crate) fn execute_pexpiretime_command(&self) -> CommandResult {
+        self.expire_time(true)
+    }
+
+    /// Replies with the Unix time the key expires at, with the same -1 and -2
+    /// as TTL.
+    fn expire_time(&self, in_ms: bool) -> CommandResult {
+        let expired_at = self.server.store.write()?.expired_at(self.args[1].bytes());
+        let time = match expired_at {
+            Some(Some(expired_at)) if in_ms => expired_at as i64,
+            Some(Some(expired_at)) => (expired_at / 1000) as i64,
+            Some(None) => -1,
+            None => -2,
+        };
+        Ok(RESP::new_integer(time))
+    }
+
+    pub(crate) fn execute_persist_command(&self) -> CommandResult {
+        let key = self.args[1].bytes();
+        let mut store = self.server.store.write()?;
+        if !matches!(store.expired_at(key), Some(Some(_))) {
+            return Ok(RESP::new_integer(0));
+        }
+        store.set_expired_at(key, None);
+        Ok(RESP::new_integer(1))
+    }
+
+    pub(crate) fn execute_keys_command(&self) -> CommandResult {
+        let pattern = self.args[1].bytes();
+        let all_keys = &pattern[..] == b"*";
+        // match after releasing the lock, so a slow pattern does not block writers
+        let keys = self.server.store.read()?.keys();
+        let keys = keys
+            .into_iter()
+            .filter(|key| all_keys || glob_match(pattern, key, false))
+            .map(RESP::new_bulk_string)
+            .collect();
+        Ok(RESP::new_array(keys))
+    }
+
+    pub(crate) fn execute_scan_command(&self) -> CommandResult {
+        let cursor = std::str::from_utf8(self.args[1].bytes())
+            .ok()
+            .and_then(|cursor| cursor.parse::<u64>().ok())
+            .ok_or_else(|| CommandError::Generic("invalid cursor".to_string()))?;
+        let mut pattern = None;
+        let mut count = 10;
+        let mut value_type = None;
+        let mut index = 2;
+        while index < self.args.len() {
+            let option = self.args[index].string().to_lowercase();
+            let next = self.args.get(index + 1).ok_or(CommandError::SyntaxError)?;
+            match &*option {
+                "match" => pattern = Some(next.bytes()).filter(|pattern| &pattern[..] != b"*"),
+                "count" => {
+                    count = next.integer().ok_or(CommandError::NotAnInteger)?;
+                    if count < 1 {
+                        return Err(CommandError::SyntaxError);
+                    }
+                }
+                "type" => {
+                    let name = next.string().to_lowercase();
+                    if !VALUE_TYPES.contains(&name.as_str()) {
+                        return Err(CommandError::Generic(format!(
+                            "unknown type name '{}'",
+                            next.string()
+                        )));
+                    }
+                    value_type = Some(name);
+                }
+                _ => return Err(CommandError::SyntaxError),
+            }
+            index += 2;
+        }
+
+        let (cursor, keys) = self.server.store.read()?.scan(cursor, count as usize);
+        // every value is a string
+        let keys = match value_type {
+            Some(value_type) if value_type != "string" => vec![],
+            _ => keys
+                .into_iter()
+                .filter(|key| pattern.is_none_or(|pattern| glob_match(pattern, key, false)))
+                .map(RESP::new_bulk_string)
+                .collect(),
+        };
+        Ok(RESP::new_array(vec![
+            RESP::new_bulk_string(cursor.to_string()),
+            RESP::new_array(keys),
+        ]))
+    }
+
+    pub(crate) fn execute_hello_command(&mut self) -> CommandResult {
+        let mut protocol = self.client.protocol;
+        let mut name = self.client.name.clone();
+        let mut index = 1;
+
+        if self.args.len() > 1 {
+            let version = self.args[1].integer().ok_or_else(|| {
+                CommandError::Generic(
+                    "Protocol version is not an integer or out of range".to_string(),
+                )
+            })?;
+            protocol = ProtocolVersion::try_from(version).map_err(|_| CommandError::NoProto)?;
+            index = 2;
+        }
+
+        while index < self.args.len() {
+            let remaining = self.args.len() - index - 1;
+            let option = self.args[index].string();
+            match &*option.to_lowercase() {
+                "auth" if remaining >= 2 => {
+                    // only the passwordless default user exists
+                    if self.args[index + 1].string() != "default" {
+                        return Err(CommandError::WrongPass);
+                    }
+                    index += 3;
+                }
+                "setname" if remaining >= 1 => {
+                    let new_name = self.args[index + 1].bytes();
+                    if new_name.iter().any(|byte| !(b'!'..=b'~').contains(byte)) {
+                        return Err(CommandError::Generic(
+                            "Client names cannot contain spaces, newlines or special characters."
+                                .to_string(),
+                        ));
+                    }
+                    name = if new_name.is_empty() {
+                        None
+                    } else {
+                        Some(new_name.clone())
+                    };
+                    index += 2;
+                }
+                _ => {
+                    return Err(CommandError::Generic(format!(
+                        "Syntax error in HELLO option '{}'",
+                        option
+                    )))
+                }
+            }
+        }
+
+        self.client.protocol = protocol;
+        self.client.name = name;
+
+        Ok(RESP::new_map(vec![
+            (
+                RESP::new_bulk_string("server"),
+                RESP::new_bulk_string("redis"),
+            ),
+            (
+                RESP::new_bulk_string("version"),
+                RESP::new_bulk_string(REDIS_VERSION),
+            ),
+            (
+                RESP::new_bulk_string("proto"),
+                RESP::new_integer(protocol.number()),
+            ),
+            (
+                RESP::new_bulk_string("id"),
+                RESP::new_integer(self.client.id as i64),
+            ),
+            (
+                RESP::new_bulk_string("mode"),
+                RESP::new_bulk_string("standalone"),
+            ),
+            (
+                RESP::new_bulk_string("role"),
+                RESP::new_bulk_string("master"),
+            ),
+            (RESP::new_bulk_string("modules"), RESP::new_array(vec![])),
+        ]))
+    }
+
+    pub(crate) fn execute_config_get_command(&self) -> CommandResult {
+        let config = self.server.config.read()?;
+
+        let pairs = PARAMETERS
+            .into_iter()
+            .filter(|name| {
+                self.args[2..]
+                    .iter()
+                    .any(|pattern| glob_match(pattern.bytes(), name.as_bytes(), true))
+            })
+            .map(|name| {
+                (
+                    RESP::new_bulk_string(name),
+                    RESP::new_bulk_string(config.get(name).unwrap_or_default()),
+                )
+            })
+            .collect();
+        Ok(RESP::new_map(pairs))
+    }
+
+    /// Applies every pair or none of them, so a bad value leaves the running
+    /// configuration untouched.
+    pub(crate) fn execute_config_set_command(&self) -> CommandResult {
+        if !self.args.len().is_multiple_of(2) {
+            return Err(wrong_number_of_arguments("config|set"));
+        }
+
+        let mut config = self.server.config.write()?;
+
+        let pairs = self.args[2..].chunks(2).collect::<Vec<&[RESP]>>();
+        let mut names: Vec<String> = vec![];
+        for pair in &pairs {
+            let name = pair[0].string().to_lowercase();
+            if !PARAMETERS.contains(&&*name) {
+                return Err(CommandError::Generic(format!(
+                    "Unknown option or number of arguments for CONFIG SET - '{}'",
+                    pair[0].string()
+                )));
+            }
+            if names.contains(&name) {
+                return Err(CommandError::Generic(format!(
+                    "Duplicate parameter - {}",
+                    name
+                )));
+            }
+            if !Config::is_mutable(&name) {
+                return Err(config_set_error(&name, "can't set immutable config"));
+            }
+            names.push(name);
+        }
+
+        let mut updated = config.clone();
+        for (name, pair) in names.iter().zip(&pairs) {
+            if let Err(message) = updated.set(name, &pair[1].string()) {
+                return Err(config_set_error(name, &message));
+            }
+        }
+
+        if updated.dir != config.dir {
+            let dir = std::env::set_current_dir(&updated.dir).and_then(|_| std::env::current_dir());
+            match dir {
+                Ok(dir) => updated.dir = dir,
+                Err(error) => return Err(config_set_error("dir", &error.to_string())),
+            }
+        }
+
+        *config = updated;
+        Ok(RESP::new_simple_string("OK"))
+    }
+
+    pub(crate) fn execute_config_rewrite_command(&self) -> CommandResult {
+        let config = self.server.config.read()?;
+        match config.rewrite() {
+            Ok(_) => Ok(RESP::new_simple_string("OK")),
+            Err(error) if config.config_file.is_none() => {
+                Err(CommandError::Generic(error.to_string()))
+            }
+            Err(error) => Err(CommandError::Generic(format!(
+                "Rewriting config file: {}",
+                error
+            ))),
+        }
+    }
+
+    pub(crate) fn execute_config_resetstat_command(&self) -> CommandResult {
+        self.server.stats.reset();
+        Ok(RESP::new_simple_string("OK"))
+    }
+
+    pub(crate) fn execute_config_help_command(&self) -> CommandResult {
+        help_reply(&[
+            "CONFIG <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+            "GET <pattern>",
+            "    Return parameters matching the glob-like <pattern> and their values.",
+            "SET <directive> <value>",
+            "    Set the configuration <directive> to <value>.",
+            "RESETSTAT",
+            "    Reset statistics reported by the INFO command.",
+            "REWRITE",
+            "    Rewrite the configuration file.",
+            "HELP",
+            "    Print this help.",
+        ])
+    }
+
+    /// Starts a graceful shutdown and closes this connection without a reply.
+    ///
+    /// Nothing is persisted yet, so SAVE, NOSAVE and FORCE have nothing to act
+    /// on, and NOW only matters with replicas. The shutdown never waits, which
+    /// leaves ABORT nothing to abort.
+    pub(crate) fn execute_shutdown_command(&mut self) -> CommandResult {
+        let (mut save, mut nosave, mut abort, mut other) = (false, false, false, false);
+        for arg in &self.args[1..] {
+            match &*arg.string().to_lowercase() {
+                "save" => save = true,
+                "nosave" => nosave = true,
+                "now" | "force" => other = true,
+                "abort" => abort = true,
+                _ => return Err(CommandError::SyntaxError),
+            }
+        }
+        if (save && nosave) || (abort && (save || nosave || other)) {
+            return Err(CommandError::SyntaxError);
+        }
+        if abort {
+            return Err(CommandError::Generic(
+                "No shutdown in progress.".to_string(),
+            ));
+        }
+
+        self.server.shutdown();
+        self.client.closing = true;
+        Ok(RESP::new_null())
+    }
+
+    pub(crate) fn execute_command_command(&self) -> CommandResult {
+        Ok(RESP::new_array(
+            COMMAND_TABLE.iter().map(|command| command.info()).collect(),
+        ))
+    }
+
+    pub(crate) fn execute_command_count_command(&self) -> CommandResult {
+        Ok(RESP::new_integer(COMMAND_TABLE.len() as i64))
+    }
+
+    /// Unknown names reply with a null entry, in the position they were asked.
+    pub(crate) fn execute_command_info_command(&self) -> CommandResult {
+        if self.args.len() == 2 {
+            return self.execute_command_command();
+        }
+
+        Ok(RESP::new_array(
+            self.args[2..]
+                .iter()
+                .map(|name| match lookup_command_name(&name.string()) {
+                    Some(command) => command.info(),
+                    None => RESP::new_null_array(),
+                })
+                .collect(),
+        ))
+    }
+
+    /// Unknown names are left out of the reply.
+    pub(crate) fn execute_command_docs_command(&self) -> CommandResult {
+        let commands: Vec<&Command> = if self.args.len() == 2 {
+            COMMAND_TABLE.iter().collect()
+        } else {
+            self.args[2..]
+                .iter()
+                .filter_map(|name| lookup_command_name(&name.string()))
+                .collect()
+        };
+
+        Ok(RESP::new_map(
+            commands
+                .into_iter()
+                .map(|command| (RESP::new_bulk_string(command.name), command.docs()))
+                .collect(),
+        ))
+    }
+
+    pub(crate) fn execute_command_getkeys_command(&self) -> CommandResult {
+        let args = &self.args[2..];
+        let mut command = match command_table::lookup(&args[0].string()) {
+            Some(command) => command,
+            None => {
+                return Err(CommandError::Generic(
+                    "Invalid command specified".to_string(),
+                ))
+            }
+        };
+        if !command.subcommands.is_empty() && args.len() >= 2 {
+            if let Some(subcommand) = command.subcommand(&args[1].string()) {
+                command = subcommand;
+            }
+        }
+        if !command.has_valid_arity(args.len()) {
+            return Err(CommandError::Generic(
+                "Invalid number of arguments specified for command".to_string(),
+            ));
+        }
+
+        let positions = command.key_positions(args.len());
+        if positions.is_empty() {
+            return Err(CommandError::Generic(
+                "The command has no key arguments".to_string(),
+            ));
+        }
+        Ok(RESP::new_array(
+            positions
+                .into_iter()
+                .map(|position| RESP::new_bulk_string(args[position].bytes().clone()))
+                .collect(),
+        ))
+    }
+
+    pub(crate) fn execute_command_help_command(&self) -> CommandResult {
+        help_reply(&[
+            "COMMAND <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+            "(no subcommand)",
+            "    Return details about all Redis commands.",
+            "COUNT",
+            "    Return the total number of commands in this Redis server.",
+            "INFO [<command-name> ...]",
+            "    Return details about multiple Redis commands.",
+            "    If no command names are given, documentation details for all",
+            "    commands are returned.",
+            "DOCS [<command-name> ...]",
+            "    Return documentation details about multiple Redis commands.",
+            "    If no command names are given, documentation details for all",
+            "    commands are returned.",
+            "GETKEYS <full-command>",
+            "    Return the keys from a full Redis command.",
+            "HELP",
+            "    Print this help.",
+        ])
+    }
+}
+
+/// Finds a command by the name COMMAND INFO and DOCS accept, where
+/// subcommands are written as `config|get`.
+fn lookup_command_name(name: &str) -> Option<&'static Command> {
+    let name = name.to_lowercase();
+    match name.split_once('|') {
+        Some((container, subcommand)) => command_table::lookup(container)?.subcommand(subcommand),
+        None => command_table::lookup(&name),
+    }
+}
+
+fn check_string_length(length: u64, max_len: u64) -> Result<(), CommandError> {
+    if length > max_len {
+        return Err(CommandError::Generic(
+            "string exceeds maximum allowed size (proto-max-bulk-len)".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// A run of bytes shared by both LCS inputs, as inclusive ranges into each.
+struct LcsRun {
+    a: (usize, usize),
+    b: (usize, usize),
+}
+
+impl LcsRun {
+    fn len(&self) -> usize {
+        self.a.1 - self.a.0 + 1
+    }
+}
 
-        let key = self.args[1].string();
-        let value = self.args[2].string();
-        let mut set_options = SetOptions::new();
-        // TODO: refactoring
-        if self.args.len() >= 3 {
-            for chunk in self.args[3..].chunks(2) {
-                match &*chunk[0].string().to_lowercase() {
-                    "px" => {
-                        let expire = chunk[1].string().parse::<u64>().unwrap();
-                        set_options.set_expire(expire);
-                    }
-                    _ => {}
-                }
-            }
+/// Finds the longest common subsequence of `a` and `b` and the runs it is
+/// made of. Runs are listed from the end of the strings, as Redis does.
+fn longest_common_subsequence(a: &[u8], b: &[u8]) -> (Vec<u8>, Vec<LcsRun>) {
+    let width = b.len() + 1;
+    let mut table = vec![0u32; (a.len() + 1) * width];
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            table[i * width + j] = if a[i - 1] == b[j - 1] {
+                table[(i - 1) * width + j - 1] + 1
+            } else {
+                table[(i - 1) * width + j].max(table[i * width + j - 1])
+            };
         }
+    }
 
-        match self.store.write() {
-            Ok(mut store) => {
-                store.set(key, value, &set_options);
-                append_simple_string(&mut bytes, "OK".to_string());
-            }
-            Err(_) => {
-                append_error(
-                    &mut bytes,
-                    "ERR".to_string(),
-                    "internal server error occurred".to_string(),
-                );
+    let mut subsequence = vec![];
+    let mut runs = vec![];
+    let mut run: Option<LcsRun> = None;
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 && j > 0 {
+        if a[i - 1] == b[j - 1] {
+            i -= 1;
+            j -= 1;
+            subsequence.push(a[i]);
+            run = Some(match run {
+                Some(run) => LcsRun {
+                    a: (i, run.a.1),
+                    b: (j, run.b.1),
+                },
+                None => LcsRun {
+                    a: (i, i),
+                    b: (j, j),
+                },
+            });
+        } else {
+            runs.extend(run.take());
+            if table[(i - 1) * width + j] > table[i * width + j - 1] {
+                i -= 1;
+            } else {
+                j -= 1;
             }
         }
     }
+    runs.extend(run.take());
+    subsequence.reverse();
+    (subsequence, runs)
+}
 
-    fn execute_get_command(&self, mut bytes: &mut Vec<u8>) {
-        if self.args.len() <= 1 {
-            append_error(
-                &mut bytes,
-                "ERR".to_string(),
-                "wrong number of arguments for 'get' command".to_string(),
-            );
-            return;
-        }
-
-        let key = self.args[1].string();
-        match self.store.read() {
-            Ok(store) => match store.get(key) {
-                Some(value) => append_bulk_string(&mut bytes, value),
-                None => append_null_string(&mut bytes),
-            },
-            Err(_) => {
-                append_error(
-                    &mut bytes,
-                    "ERR".to_string(),
-                    "internal server error occurred".to_string(),
-                );
-            }
-        }
+fn keep_ttl() -> SetOptions {
+    let mut options = SetOptions::new();
+    options.set_expiration(Expiration::KeepTtl);
+    options
+}
+
+/// Parses a float the way Redis' `string2ld` does: no surrounding spaces,
+/// no NaN, and no values that overflow to infinity.
+fn parse_float(data: &[u8]) -> Option<f64> {
+    let text = std::str::from_utf8(data).ok()?;
+    if text.starts_with(|c: char| c.is_ascii_whitespace()) {
+        return None;
+    }
+    let value = text.parse::<f64>().ok()?;
+    if value.is_nan() || (value.is_infinite() && !text.to_lowercase().contains("inf")) {
+        return None;
     }
+    Some(value)
 }
 
-fn append_simple_string(bytes: &mut Vec<u8>, value: String) {
-    let mut data = [SIMPLE_STRING.to_string(), value, CRLF.to_string()]
-        .join("")
-        .as_bytes()
-        .to_vec();
-    bytes.append(&mut data);
+/// Formats a float the way INCRBYFLOAT replies and stores it, like Redis's
+/// `ld2string` with `LD_STR_HUMAN`: fixed notation with at most 17 decimals,
+/// then trailing zeros and `.` trimmed.
+///
+/// Redis adds in long double, whose extra precision rounds away the error of
+/// sums like 0.1 + 0.2. An f64 is rounded to the 15 significant digits it
+/// carries reliably to print the same "0.3".
+fn format_float(value: f64) -> String {
+    // 0 has a magnitude of -inf, which saturates to the most decimals
+    let magnitude = value.abs().log10().floor() as i64;
+    let decimals = (f64::DIGITS as i64 - 1)
+        .saturating_sub(magnitude)
+        .clamp(0, 17) as usize;
+    let formatted = format!("{:.*}", decimals, value);
+    let trimmed = if formatted.contains('.') {
+        formatted.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        &formatted
+    };
+    match trimmed {
+        // no "-0"
+        "-0" => "0".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// Turns the argument of an `EX`, `PX`, `EXAT` or `PXAT` option into the Unix
+/// time in milliseconds at which the key expires, `now` being the current one.
+fn parse_expiration(
+    unit: &str,
+    value: &RESP,
+    command: &str,
+    now: u128,
+) -> Result<u128, CommandError> {
+    let value = value.integer().ok_or(CommandError::NotAnInteger)?;
+    let invalid = || CommandError::InvalidExpireTime(command.to_string());
+    if value <= 0 {
+        return Err(invalid());
+    }
+
+    let milliseconds = match unit {
+        "ex" | "exat" => value.checked_mul(1000).ok_or_else(invalid)?,
+        _ => value,
+    };
+    let expired_at = match unit {
+        "ex" | "px" => milliseconds.checked_add(now as i64).ok_or_else(invalid)?,
+        _ => milliseconds,
+    };
+    Ok(expired_at as u128)
+}
+
+/// Quotes the first arguments of an unknown command the way Redis does,
+/// stopping once the preview reaches 128 bytes.
+fn unknown_command(args: &[RESP]) -> CommandError {
+    let mut preview = vec![];
+    for arg in &args[1..] {
+        if preview.len() >= 128 {
+            break;
+        }
+        let arg = arg.bytes();
+        let arg = &arg[..arg.len().min(128 - preview.len())];
+        preview.push(b'\'');
+        preview.extend_from_slice(arg);
+        preview.extend_from_slice(b"' ");
+    }
+    CommandError::UnknownCommand(
+        truncate(args[0].bytes(), 128),
+        String::from_utf8_lossy(&preview).into_owned(),
+    )
 }
 
-fn append_bulk_string(bytes: &mut Vec<u8>, value: String) {
-    let string_bytes = value.as_bytes();
-    let mut data = [
-        BULK_STRING.to_string(),
-        string_bytes.len().to_string(),
-        CRLF.to_string(),
-        value,
-        CRLF.to_string(),
-    ]
-    .join("")
-    .as_bytes()
-    .to_vec();
-    bytes.append(&mut data);
+/// Cuts `value` to `max_len` bytes like `%.*s`, replacing a character split
+/// by the cut.
+fn truncate(value: &[u8], max_len: usize) -> String {
+    String::from_utf8_lossy(&value[..value.len().min(max_len)]).into_owned()
 }
 
-fn append_null_string(bytes: &mut Vec<u8>) {
-    bytes.append(&mut NULL_STRING.as_bytes().to_vec());
+fn wrong_number_of_arguments(name: &str) -> CommandError {
+    CommandError::WrongNumberOfArguments(name.to_string())
 }
 
-#[allow(dead_code)]
-fn append_integer(bytes: &mut Vec<u8>, int: i64) {
-    let mut data = [INTEGER.to_string(), int.to_string(), CRLF.to_string()]
-        .join("")
-        .as_bytes()
-        .to_vec();
-    bytes.append(&mut data);
+fn help_reply(lines: &[&str]) -> CommandResult {
+    Ok(RESP::new_array(
+        lines
+            .iter()
+            .map(|line| RESP::new_simple_string(line))
+            .collect(),
+    ))
 }
 
-fn append_error(bytes: &mut Vec<u8>, error_type: String, message: String) {
-    let mut data = [
-        ERROR.to_string(),
-        error_type,
-        " ".to_string(),
-        message,
-        CRLF.to_string(),
-    ]
-    .join("")
-    .as_bytes()
-    .to_vec();
-    bytes.append(&mut data);
+fn config_set_error(name: &str, message: &str) -> CommandError {
+    CommandError::Generic(format!(
+        "CONFIG SET failed (possibly related to argument '{}') - {}",
+        name, message
+    ))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::CommandExecutor;
-    use crate::resp::NULL_STRING;
-    use crate::resp_decoder::RESPDecoder;
-    use crate::store::Store;
-    use std::sync::RwLock;
+    use super::{sanitize_error_message, CommandExecutor};
+    use crate::client::Client;
+    use crate::clock::ManualClock;
+    use crate::command_table::COMMAND_TABLE;
+    use crate::config::Config;
+    use crate::resp::{ProtocolVersion, Type, NULL_STRING, RESP3_NULL};
+    use crate::resp_decoder::{DecodeError, RESPDecoder};
+    use crate::server::Server;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
     use std::thread;
     use std::time::Duration;
 
+    /// Where the `ManualClock` of the expiry tests starts, any time after
+    /// the `PXAT 1` some of them use.
+    const START_TIME: u64 = 1_700_000_000_000;
+
     #[test]
     fn it_should_execute_ping() {
-        let store = RwLock::new(Store::new());
+        let server = Server::new(Config::default());
         let command = b"*1\r\n$4\r\nping\r\n";
-        let response = execute_command(command.to_vec(), &store);
+        let response = execute_command(command.to_vec(), &server);
         assert_eq!(String::from_utf8_lossy(&response[..]), "+PONG\r\n");
     }
 
     #[test]
     fn it_should_execute_echo() {
-        let store = RwLock::new(Store::new());
+        let server = Server::new(Config::default());
         let command = b"*2\r\n$4\r\necho\r\n$5\r\nhello\r\n";
-        let response = execute_command(command.to_vec(), &store);
+        let response = execute_command(command.to_vec(), &server);
         assert_eq!(String::from_utf8_lossy(&response[..]), "$5\r\nhello\r\n");
     }
 
     #[test]
     fn it_should_execute_set() {
-        let store = RwLock::new(Store::new());
+        let server = Server::new(Config::default());
         let command = b"*3\r\n$3\r\nset\r\n$3\r\nkey\r\n$5\r\nvalue\r\n";
-        let response = execute_command(command.to_vec(), &store);
+        let response = execute_command(command.to_vec(), &server);
         assert_eq!(String::from_utf8_lossy(&response[..]), "+OK\r\n");
     }
 
     #[test]
     fn it_should_execute_get() {
-        let store = RwLock::new(Store::new());
+        let server = Server::new(Config::default());
         let set_command = b"*3\r\n$3\r\nset\r\n$3\r\nkey\r\n$5\r\nvalue\r\n";
-        execute_command(set_command.to_vec(), &store);
+        execute_command(set_command.to_vec(), &server);
 
         let get_command = b"*2\r\n$3\r\nget\r\n$3\r\nkey\r\n";
-        let response = execute_command(get_command.to_vec(), &store);
+        let response = execute_command(get_command.to_vec(), &server);
         assert_eq!(String::from_utf8_lossy(&response[..]), "$5\r\nvalue\r\n");
     }
 
+    #[test]
+    fn it_should_round_trip_binary_values() {
+        let server = Server::new(Config::default());
+        let set_command = b"*3\r\n$3\r\nset\r\n$2\r\n\xfe\x00\r\n$4\r\n\x00\xff\r\n\r\n";
+        execute_command(set_command.to_vec(), &server);
+
+        let get_command = b"*2\r\n$3\r\nget\r\n$2\r\n\xfe\x00\r\n";
+        let response = execute_command(get_command.to_vec(), &server);
+        assert_eq!(response, b"$4\r\n\x00\xff\r\n\r\n");
+    }
+
     #[test]
     fn it_should_return_null_when_value_expires() {
-        let store = RwLock::new(Store::new());
+        let clock = Arc::new(ManualClock::new(START_TIME));
+        let server = Server::with_clock(Config::default(), clock.clone());
         let set_command =
             b"*5\r\n$3\r\nset\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$2\r\npx\r\n$3\r\n100\r\n";
-        execute_command(set_command.to_vec(), &store);
+        execute_command(set_command.to_vec(), &server);
 
-        let times = Duration::from_millis(101);
-        thread::sleep(times);
+        clock.advance(Duration::from_millis(101));
 
         let get_command = b"*2\r\n$3\r\nget\r\n$3\r\nkey\r\n";
-        let response = execute_command(get_command.to_vec(), &store);
+        let response = execute_command(get_command.to_vec(), &server);
+        assert_eq!(String::from_utf8_lossy(&response[..]), NULL_STRING);
+    }
+
+    #[test]
+    fn it_should_set_conditionally_and_return_the_old_value() {
+        let server = Server::new(Config::default());
+        let tests = [
+            (
+                "*4\r\n$3\r\nset\r\n$1\r\nk\r\n$1\r\na\r\n$2\r\nXX\r\n",
+                "$-1\r\n",
+            ),
+            (
+                "*4\r\n$3\r\nset\r\n$1\r\nk\r\n$1\r\na\r\n$2\r\nnx\r\n",
+                "+OK\r\n",
+            ),
+            (
+                "*4\r\n$3\r\nset\r\n$1\r\nk\r\n$1\r\nb\r\n$2\r\nnx\r\n",
+                "$-1\r\n",
+            ),
+            (
+                "*5\r\n$3\r\nset\r\n$1\r\nk\r\n$1\r\nb\r\n$2\r\nnx\r\n$3\r\nget\r\n",
+                "$1\r\na\r\n",
+            ),
+            (
+                "*5\r\n$3\r\nset\r\n$1\r\nk\r\n$1\r\nc\r\n$2\r\nxx\r\n$3\r\nget\r\n",
+                "$1\r\na\r\n",
+            ),
+            (
+                "*4\r\n$3\r\nset\r\n$1\r\nn\r\n$1\r\nd\r\n$3\r\nget\r\n",
+                "$-1\r\n",
+            ),
+            ("*2\r\n$3\r\nget\r\n$1\r\nk\r\n", "$1\r\nc\r\n"),
+            (
+                "*5\r\n$3\r\nset\r\n$1\r\nk\r\n$1\r\nv\r\n$4\r\nPXAT\r\n$1\r\n1\r\n",
+                "+OK\r\n",
+            ),
+            ("*2\r\n$3\r\nget\r\n$1\r\nk\r\n", "$-1\r\n"),
+        ];
+
+        for test in tests {
+            let response = execute_command(test.0.as_bytes().to_vec(), &server);
+            assert_eq!(String::from_utf8_lossy(&response[..]), test.1, "{}", test.0);
+        }
+    }
+
+    #[test]
+    fn it_should_reject_conflicting_set_options() {
+        let tests = [
+            "*5\r\n$3\r\nset\r\n$1\r\nk\r\n$1\r\nv\r\n$2\r\nnx\r\n$2\r\nxx\r\n",
+            "*7\r\n$3\r\nset\r\n$1\r\nk\r\n$1\r\nv\r\n$2\r\nex\r\n$1\r\n1\r\n$2\r\npx\r\n$1\r\n1\r\n",
+            "*6\r\n$3\r\nset\r\n$1\r\nk\r\n$1\r\nv\r\n$7\r\nkeepttl\r\n$4\r\nexat\r\n$1\r\n1\r\n",
+            "*6\r\n$3\r\nset\r\n$1\r\nk\r\n$1\r\nv\r\n$2\r\nex\r\n$1\r\n1\r\n$7\r\nkeepttl\r\n",
+        ];
+        for test in tests {
+            let server = Server::new(Config::default());
+            let response = execute_command(test.as_bytes().to_vec(), &server);
+            assert_eq!(
+                String::from_utf8_lossy(&response[..]),
+                "-ERR syntax error\r\n"
+            );
+        }
+
+        let server = Server::new(Config::default());
+        let command =
+            b"*5\r\n$3\r\nset\r\n$1\r\nk\r\n$1\r\nv\r\n$2\r\nex\r\n$19\r\n9223372036854775807\r\n";
+        let response = execute_command(command.to_vec(), &server);
+        assert_eq!(
+            String::from_utf8_lossy(&response[..]),
+            "-ERR invalid expire time in 'set' command\r\n"
+        );
+    }
+
+    #[test]
+    fn it_should_clear_the_ttl_unless_keepttl_is_given() {
+        let clock = Arc::new(ManualClock::new(START_TIME));
+        let server = Server::with_clock(Config::default(), clock.clone());
+        let set_with_ttl = b"*5\r\n$3\r\nset\r\n$1\r\na\r\n$1\r\n1\r\n$2\r\npx\r\n$3\r\n100\r\n";
+        execute_command(set_with_ttl.to_vec(), &server);
+        execute_command(
+            b"*3\r\n$3\r\nset\r\n$1\r\na\r\n$1\r\n2\r\n".to_vec(),
+            &server,
+        );
+
+        let set_with_ttl = b"*5\r\n$3\r\nset\r\n$1\r\nb\r\n$1\r\n1\r\n$2\r\npx\r\n$3\r\n100\r\n";
+        execute_command(set_with_ttl.to_vec(), &server);
+        let keep_ttl = b"*4\r\n$3\r\nset\r\n$1\r\nb\r\n$1\r\n2\r\n$7\r\nkeepttl\r\n";
+        execute_command(keep_ttl.to_vec(), &server);
+
+        clock.advance(Duration::from_millis(101));
+
+        let response = execute_command(b"*2\r\n$3\r\nget\r\n$1\r\na\r\n".to_vec(), &server);
+        assert_eq!(String::from_utf8_lossy(&response[..]), "$1\r\n2\r\n");
+        let response = execute_command(b"*2\r\n$3\r\nget\r\n$1\r\nb\r\n".to_vec(), &server);
         assert_eq!(String::from_utf8_lossy(&response[..]), NULL_STRING);
     }
 
+    #[test]
+    fn it_should_increment_and_decrement_counters() {
+        let server = Server::new(Config::default());
+        let tests = [
+            ("*2\r\n$4\r\nincr\r\n$1\r\nn\r\n", ":1\r\n"),
+            ("*3\r\n$6\r\nincrby\r\n$1\r\nn\r\n$2\r\n10\r\n", ":11\r\n"),
+            ("*2\r\n$4\r\ndecr\r\n$1\r\nn\r\n", ":10\r\n"),
+            ("*3\r\n$6\r\ndecrby\r\n$1\r\nn\r\n$2\r\n-5\r\n", ":15\r\n"),
+            (
+                "*3\r\n$6\r\nincrby\r\n$1\r\nn\r\n$3\r\n1.5\r\n",
+                "-ERR value is not an integer or out of range\r\n",
+            ),
+            (
+                "*3\r\n$6\r\ndecrby\r\n$1\r\nn\r\n$20\r\n-9223372036854775808\r\n",
+                "-ERR decrement would overflow\r\n",
+            ),
+            (
+                "*3\r\n$3\r\nset\r\n$1\r\nm\r\n$19\r\n9223372036854775807\r\n",
+                "+OK\r\n",
+            ),
+            (
+                "*2\r\n$4\r\nincr\r\n$1\r\nm\r\n",
+                "-ERR increment or decrement would overflow\r\n",
+            ),
+            ("*3\r\n$3\r\nset\r\n$1\r\ns\r\n$3\r\n 12\r\n", "+OK\r\n"),
+            (
+                "*2\r\n$4\r\nincr\r\n$1\r\ns\r\n",
+                "-ERR value is not an integer or out of range\r\n",
+            ),
+        ];
+
+        for test in tests {
+            let response = execute_command(test.0.as_bytes().to_vec(), &server);
+            assert_eq!(String::from_utf8_lossy(&response[..]), test.1, "{}", test.0);
+        }
+    }
+
+    #[test]
+    fn it_should_increment_by_float() {
+        let server = Server::new(Config::default());
+        let tests = [
+            (
+                "*3\r\n$11\r\nincrbyfloat\r\n$1\r\nf\r\n$4\r\n10.5\r\n",
+                "$4\r\n10.5\r\n",
+            ),
+            (
+                "*3\r\n$11\r\nincrbyfloat\r\n$1\r\nf\r\n$3\r\n0.1\r\n",
+                "$4\r\n10.6\r\n",
+            ),
+            (
+                "*3\r\n$11\r\nincrbyfloat\r\n$1\r\nf\r\n$5\r\n-10.6\r\n",
+                "$1\r\n0\r\n",
+            ),
+            (
+                "*3\r\n$11\r\nincrbyfloat\r\n$1\r\nf\r\n$5\r\n5.0e3\r\n",
+                "$4\r\n5000\r\n",
+            ),
+            (
+                "*3\r\n$11\r\nincrbyfloat\r\n$1\r\nf\r\n$3\r\nabc\r\n",
+                "-ERR value is not a valid float\r\n",
+            ),
+            (
+                "*3\r\n$11\r\nincrbyfloat\r\n$1\r\nf\r\n$3\r\ninf\r\n",
+                "-ERR increment would produce NaN or Infinity\r\n",
+            ),
+            ("*2\r\n$3\r\nget\r\n$1\r\nf\r\n", "$4\r\n5000\r\n"),
+            ("*3\r\n$3\r\nset\r\n$1\r\ng\r\n$3\r\n0.1\r\n", "+OK\r\n"),
+            (
+                "*3\r\n$11\r\nincrbyfloat\r\n$1\r\ng\r\n$3\r\n0.2\r\n",
+                "$3\r\n0.3\r\n",
+            ),
+        ];
+
+        for test in tests {
+            let response = execute_command(test.0.as_bytes().to_vec(), &server);
+            assert_eq!(String::from_utf8_lossy(&response[..]), test.1, "{}", test.0);
+        }
+    }
+
+    #[test]
+    fn it_should_not_lose_concurrent_increments() {
+        let server = Server::new(Config::default());
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        execute_command(b"*2\r\n$4\r\nincr\r\n$1\r\nc\r\n".to_vec(), &server);
+                    }
+                });
+            }
+        });
+
+        let response = execute_command(b"*2\r\n$3\r\nget\r\n$1\r\nc\r\n".to_vec(), &server);
+        assert_eq!(String::from_utf8_lossy(&response[..]), "$3\r\n800\r\n");
+    }
+
+    #[test]
+    fn it_should_append_and_measure_strings() {
+        let server = Server::new(Config::default());
+        let tests = [
+            ("*2\r\n$6\r\nstrlen\r\n$1\r\ns\r\n", ":0\r\n"),
+            ("*3\r\n$6\r\nappend\r\n$1\r\ns\r\n$5\r\nHello\r\n", ":5\r\n"),
+            (
+                "*3\r\n$6\r\nappend\r\n$1\r\ns\r\n$6\r\n World\r\n",
+                ":11\r\n",
+            ),
+            ("*2\r\n$6\r\nstrlen\r\n$1\r\ns\r\n", ":11\r\n"),
+            ("*2\r\n$3\r\nget\r\n$1\r\ns\r\n", "$11\r\nHello World\r\n"),
+        ];
+
+        for test in tests {
+            let response = execute_command(test.0.as_bytes().to_vec(), &server);
+            assert_eq!(String::from_utf8_lossy(&response[..]), test.1, "{}", test.0);
+        }
+    }
+
+    #[test]
+    fn it_should_get_ranges_with_negative_offsets() {
+        let server = Server::new(Config::default());
+        let set_command = b"*3\r\n$3\r\nset\r\n$1\r\ns\r\n$16\r\nThis is a string\r\n";
+        execute_command(set_command.to_vec(), &server);
+
+        let tests = [
+            ("0", "3", "This"),
+            ("-3", "-1", "ing"),
+            ("0", "-1", "This is a string"),
+            ("10", "100", "string"),
+            ("5", "3", ""),
+            ("-1", "-5", ""),
+            ("-100", "2", "Thi"),
+        ];
+        for (start, end, expected) in tests {
+            for name in ["getrange", "substr"] {
+                let command = format!(
+                    "*4\r\n${}\r\n{}\r\n$1\r\ns\r\n${}\r\n{}\r\n${}\r\n{}\r\n",
+                    name.len(),
+                    name,
+                    start.len(),
+                    start,
+                    end.len(),
+                    end
+                );
+                let response = execute_command(command.into_bytes(), &server);
+                assert_eq!(
+                    String::from_utf8_lossy(&response[..]),
+                    format!("${}\r\n{}\r\n", expected.len(), expected)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn it_should_set_ranges_with_zero_padding() {
+        let server = Server::new(Config::default());
+        let tests = [
+            (
+                "*4\r\n$8\r\nsetrange\r\n$1\r\ns\r\n$1\r\n3\r\n$0\r\n\r\n",
+                ":0\r\n",
+            ),
+            ("*2\r\n$3\r\nget\r\n$1\r\ns\r\n", "$-1\r\n"),
+            (
+                "*4\r\n$8\r\nsetrange\r\n$1\r\ns\r\n$1\r\n3\r\n$2\r\nab\r\n",
+                ":5\r\n",
+            ),
+            ("*2\r\n$3\r\nget\r\n$1\r\ns\r\n", "$5\r\n\0\0\0ab\r\n"),
+            (
+                "*4\r\n$8\r\nsetrange\r\n$1\r\ns\r\n$1\r\n0\r\n$1\r\nx\r\n",
+                ":5\r\n",
+            ),
+            ("*2\r\n$3\r\nget\r\n$1\r\ns\r\n", "$5\r\nx\0\0ab\r\n"),
+            (
+                "*4\r\n$8\r\nsetrange\r\n$1\r\ns\r\n$2\r\n-1\r\n$1\r\nx\r\n",
+                "-ERR offset is out of range\r\n",
+            ),
+            (
+                "*4\r\n$8\r\nsetrange\r\n$1\r\ns\r\n$9\r\n536870912\r\n$1\r\nx\r\n",
+                "-ERR string exceeds maximum allowed size (proto-max-bulk-len)\r\n",
+            ),
+        ];
+
+        for test in tests {
+            let response = execute_command(test.0.as_bytes().to_vec(), &server);
+            assert_eq!(String::from_utf8_lossy(&response[..]), test.1, "{}", test.0);
+        }
+    }
+
+    #[test]
+    fn it_should_get_and_set_multiple_keys() {
+        let server = Server::new(Config::default());
+        let tests = [
+            (
+                "*5\r\n$4\r\nmset\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nb\r\n$1\r\n2\r\n",
+                "+OK\r\n",
+            ),
+            (
+                "*4\r\n$4\r\nmget\r\n$1\r\na\r\n$1\r\nx\r\n$1\r\nb\r\n",
+                "*3\r\n$1\r\n1\r\n$-1\r\n$1\r\n2\r\n",
+            ),
+            (
+                "*4\r\n$4\r\nmset\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nb\r\n",
+                "-ERR wrong number of arguments for 'mset' command\r\n",
+            ),
+            (
+                "*5\r\n$6\r\nmsetnx\r\n$1\r\nc\r\n$1\r\n3\r\n$1\r\na\r\n$1\r\n9\r\n",
+                ":0\r\n",
+            ),
+            (
+                "*3\r\n$4\r\nmget\r\n$1\r\na\r\n$1\r\nc\r\n",
+                "*2\r\n$1\r\n1\r\n$-1\r\n",
+            ),
+            (
+                "*5\r\n$6\r\nmsetnx\r\n$1\r\nc\r\n$1\r\n3\r\n$1\r\nd\r\n$1\r\n4\r\n",
+                ":1\r\n",
+            ),
+            (
+                "*3\r\n$4\r\nmget\r\n$1\r\nc\r\n$1\r\nd\r\n",
+                "*2\r\n$1\r\n3\r\n$1\r\n4\r\n",
+            ),
+        ];
+
+        for test in tests {
+            let response = execute_command(test.0.as_bytes().to_vec(), &server);
+            assert_eq!(String::from_utf8_lossy(&response[..]), test.1, "{}", test.0);
+        }
+    }
+
+    #[test]
+    fn it_should_get_and_delete_or_replace() {
+        let server = Server::new(Config::default());
+        let tests = [
+            ("*3\r\n$5\r\nsetnx\r\n$1\r\nk\r\n$1\r\na\r\n", ":1\r\n"),
+            ("*3\r\n$5\r\nsetnx\r\n$1\r\nk\r\n$1\r\nb\r\n", ":0\r\n"),
+            (
+                "*3\r\n$6\r\ngetset\r\n$1\r\nk\r\n$1\r\nc\r\n",
+                "$1\r\na\r\n",
+            ),
+            ("*3\r\n$6\r\ngetset\r\n$1\r\nn\r\n$1\r\nc\r\n", "$-1\r\n"),
+            ("*2\r\n$6\r\ngetdel\r\n$1\r\nk\r\n", "$1\r\nc\r\n"),
+            ("*2\r\n$6\r\ngetdel\r\n$1\r\nk\r\n", "$-1\r\n"),
+            ("*2\r\n$3\r\nget\r\n$1\r\nk\r\n", "$-1\r\n"),
+            (
+                "*4\r\n$5\r\nsetex\r\n$1\r\nk\r\n$1\r\n0\r\n$1\r\nv\r\n",
+                "-ERR invalid expire time in 'setex' command\r\n",
+            ),
+            (
+                "*4\r\n$6\r\npsetex\r\n$1\r\nk\r\n$2\r\nab\r\n$1\r\nv\r\n",
+                "-ERR value is not an integer or out of range\r\n",
+            ),
+            (
+                "*4\r\n$5\r\nsetex\r\n$1\r\nk\r\n$2\r\n10\r\n$1\r\nv\r\n",
+                "+OK\r\n",
+            ),
+            ("*2\r\n$3\r\nget\r\n$1\r\nk\r\n", "$1\r\nv\r\n"),
+        ];
+
+        for test in tests {
+            let response = execute_command(test.0.as_bytes().to_vec(), &server);
+            assert_eq!(String::from_utf8_lossy(&response[..]), test.1, "{}", test.0);
+        }
+    }
+
+    #[test]
+    fn it_should_change_the_ttl_with_getex() {
+        let clock = Arc::new(ManualClock::new(START_TIME));
+        let server = Server::with_clock(Config::default(), clock.clone());
+        let tests = [
+            ("*2\r\n$5\r\ngetex\r\n$1\r\nk\r\n", "$-1\r\n"),
+            (
+                "*4\r\n$6\r\npsetex\r\n$1\r\nk\r\n$3\r\n100\r\n$1\r\nv\r\n",
+                "+OK\r\n",
+            ),
+            (
+                "*3\r\n$5\r\ngetex\r\n$1\r\nk\r\n$7\r\npersist\r\n",
+                "$1\r\nv\r\n",
+            ),
+            ("*3\r\n$3\r\nset\r\n$1\r\nx\r\n$1\r\ny\r\n", "+OK\r\n"),
+            (
+                "*4\r\n$5\r\ngetex\r\n$1\r\nx\r\n$4\r\npxat\r\n$1\r\n1\r\n",
+                "$1\r\ny\r\n",
+            ),
+            ("*2\r\n$3\r\nget\r\n$1\r\nx\r\n", "$-1\r\n"),
+            (
+                "*5\r\n$5\r\ngetex\r\n$1\r\nk\r\n$7\r\npersist\r\n$2\r\nex\r\n$1\r\n1\r\n",
+                "-ERR syntax error\r\n",
+            ),
+            (
+                "*3\r\n$5\r\ngetex\r\n$1\r\nk\r\n$2\r\nnx\r\n",
+                "-ERR syntax error\r\n",
+            ),
+            (
+                "*4\r\n$5\r\ngetex\r\n$1\r\nk\r\n$2\r\nex\r\n$2\r\n-1\r\n",
+                "-ERR invalid expire time in 'getex' command\r\n",
+            ),
+        ];
+        for test in tests {
+            let response = execute_command(test.0.as_bytes().to_vec(), &server);
+            assert_eq!(String::from_utf8_lossy(&response[..]), test.1, "{}", test.0);
+        }
+
+        clock.advance(Duration::from_millis(101));
+        let response = execute_command(b"*2\r\n$3\r\nget\r\n$1\r\nk\r\n".to_vec(), &server);
+        assert_eq!(String::from_utf8_lossy(&response[..]), "$1\r\nv\r\n");
+    }
+
+    #[test]
+    fn it_should_find_the_longest_common_subsequence() {
+        let server = Server::new(Config::default());
+        execute_command(
+            b"*5\r\n$4\r\nmset\r\n$2\r\nk1\r\n$8\r\nohmytext\r\n$2\r\nk2\r\n$9\r\nmynewtext\r\n"
+                .to_vec(),
+            &server,
+        );
+        let tests = [
+            ("*3\r\n$3\r\nlcs\r\n$2\r\nk1\r\n$2\r\nk2\r\n", "$6\r\nmytext\r\n"),
+            ("*4\r\n$3\r\nlcs\r\n$2\r\nk1\r\n$2\r\nk2\r\n$3\r\nlen\r\n", ":6\r\n"),
+            ("*3\r\n$3\r\nlcs\r\n$2\r\nk1\r\n$1\r\nx\r\n", "$0\r\n\r\n"),
+            (
+                "*4\r\n$3\r\nlcs\r\n$2\r\nk1\r\n$2\r\nk2\r\n$3\r\nidx\r\n",
+                "*4\r\n$7\r\nmatches\r\n*2\r\n*2\r\n*2\r\n:4\r\n:7\r\n*2\r\n:5\r\n:8\r\n*2\r\n*2\r\n:2\r\n:3\r\n*2\r\n:0\r\n:1\r\n$3\r\nlen\r\n:6\r\n",
+            ),
+            (
+                "*7\r\n$3\r\nlcs\r\n$2\r\nk1\r\n$2\r\nk2\r\n$3\r\nidx\r\n$11\r\nminmatchlen\r\n$1\r\n4\r\n$12\r\nwithmatchlen\r\n",
+                "*4\r\n$7\r\nmatches\r\n*1\r\n*3\r\n*2\r\n:4\r\n:7\r\n*2\r\n:5\r\n:8\r\n:4\r\n$3\r\nlen\r\n:6\r\n",
+            ),
+            (
+                "*5\r\n$3\r\nlcs\r\n$2\r\nk1\r\n$2\r\nk2\r\n$3\r\nlen\r\n$3\r\nidx\r\n",
+                "-ERR If you want both the length and indexes, please just use IDX.\r\n",
+            ),
+            (
+                "*4\r\n$3\r\nlcs\r\n$2\r\nk1\r\n$2\r\nk2\r\n$11\r\nminmatchlen\r\n",
+                "-ERR syntax error\r\n",
+            ),
+        ];
+
+        for test in tests {
+            let response = execute_command(test.0.as_bytes().to_vec(), &server);
+            assert_eq!(String::from_utf8_lossy(&response[..]), test.1, "{}", test.0);
+        }
+    }
+
+    #[test]
+    fn it_should_execute_generic_key_commands() {
+        let server = Server::new(Config::default());
+        let tests = [
+            ("*5\r\n$4\r\nmset\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nb\r\n$1\r\n2\r\n", "+OK\r\n"),
+            ("*5\r\n$6\r\nexists\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\na\r\n$1\r\nc\r\n", ":3\r\n"),
+            ("*3\r\n$5\r\ntouch\r\n$1\r\na\r\n$1\r\nc\r\n", ":1\r\n"),
+            ("*2\r\n$4\r\ntype\r\n$1\r\na\r\n", "+string\r\n"),
+            ("*2\r\n$4\r\ntype\r\n$1\r\nc\r\n", "+none\r\n"),
+            ("*4\r\n$3\r\ndel\r\n$1\r\na\r\n$1\r\na\r\n$1\r\nc\r\n", ":1\r\n"),
+            ("*2\r\n$6\r\nunlink\r\n$1\r\nb\r\n", ":1\r\n"),
+            ("*3\r\n$6\r\nexists\r\n$1\r\na\r\n$1\r\nb\r\n", ":0\r\n"),
+            ("*3\r\n$6\r\nrename\r\n$1\r\na\r\n$1\r\nb\r\n", "-ERR no such key\r\n"),
+            ("*3\r\n$3\r\nset\r\n$1\r\na\r\n$1\r\n1\r\n", "+OK\r\n"),
+            ("*3\r\n$3\r\nset\r\n$1\r\nb\r\n$1\r\n2\r\n", "+OK\r\n"),
+            ("*3\r\n$8\r\nrenamenx\r\n$1\r\na\r\n$1\r\nb\r\n", ":0\r\n"),
+            ("*3\r\n$6\r\nrename\r\n$1\r\na\r\n$1\r\nb\r\n", "+OK\r\n"),
+            ("*2\r\n$3\r\nget\r\n$1\r\nb\r\n", "$1\r\n1\r\n"),
+            ("*2\r\n$6\r\nexists\r\n$1\r\na\r\n", ":0\r\n"),
+            ("*3\r\n$8\r\nrenamenx\r\n$1\r\nb\r\n$1\r\na\r\n", ":1\r\n"),
+            ("*3\r\n$4\r\ncopy\r\n$1\r\na\r\n$1\r\na\r\n", "-ERR source and destination objects are the same\r\n"),
+            ("*5\r\n$4\r\ncopy\r\n$1\r\na\r\n$1\r\nb\r\n$2\r\ndb\r\n$1\r\n1\r\n", "-ERR DB index is out of range\r\n"),
+            ("*5\r\n$4\r\ncopy\r\n$1\r\na\r\n$1\r\nb\r\n$2\r\ndb\r\n$1\r\nx\r\n", "-ERR value is not an integer or out of range\r\n"),
+            ("*4\r\n$4\r\ncopy\r\n$1\r\na\r\n$1\r\nb\r\n$2\r\nnx\r\n", "-ERR syntax error\r\n"),
+            ("*3\r\n$4\r\ncopy\r\n$1\r\nc\r\n$1\r\nb\r\n", ":0\r\n"),
+            ("*3\r\n$4\r\ncopy\r\n$1\r\na\r\n$1\r\nb\r\n", ":1\r\n"),
+            ("*3\r\n$3\r\nset\r\n$1\r\na\r\n$1\r\n3\r\n", "+OK\r\n"),
+            ("*3\r\n$4\r\ncopy\r\n$1\r\na\r\n$1\r\nb\r\n", ":0\r\n"),
+            ("*6\r\n$4\r\ncopy\r\n$1\r\na\r\n$1\r\nb\r\n$2\r\ndb\r\n$1\r\n0\r\n$7\r\nreplace\r\n", ":1\r\n"),
+            ("*2\r\n$3\r\nget\r\n$1\r\nb\r\n", "$1\r\n3\r\n"),
+        ];
+
+        for test in tests {
+            let response = execute_command(test.0.as_bytes().to_vec(), &server);
+            assert_eq!(String::from_utf8_lossy(&response[..]), test.1, "{}", test.0);
+        }
+    }
+
+    #[test]
+    fn it_should_keep_the_ttl_on_rename_and_copy() {
+        let clock = Arc::new(ManualClock::new(START_TIME));
+        let server = Server::with_clock(Config::default(), clock.clone());
+        let tests = [
+            (
+                "*5\r\n$3\r\nset\r\n$1\r\na\r\n$1\r\n1\r\n$2\r\npx\r\n$3\r\n100\r\n",
+                "+OK\r\n",
+            ),
+            ("*3\r\n$4\r\ncopy\r\n$1\r\na\r\n$1\r\nb\r\n", ":1\r\n"),
+            ("*3\r\n$6\r\nrename\r\n$1\r\na\r\n$1\r\nc\r\n", "+OK\r\n"),
+        ];
+        for test in tests {
+            let response = execute_command(test.0.as_bytes().to_vec(), &server);
+            assert_eq!(String::from_utf8_lossy(&response[..]), test.1, "{}", test.0);
+        }
+
+        clock.advance(Duration::from_millis(101));
+        let response = execute_command(
+            b"*3\r\n$6\r\nexists\r\n$1\r\nb\r\n$1\r\nc\r\n".to_vec(),
+            &server,
+        );
+        assert_eq!(String::from_utf8_lossy(&response[..]), ":0\r\n");
+    }
+
+    #[test]
+    fn it_should_manage_expiry() {
+        let clock = Arc::new(ManualClock::new(START_TIME));
+        let server = Server::with_clock(Config::default(), clock);
+        let tests = [
+            ("*3\r\n$3\r\nset\r\n$1\r\nk\r\n$1\r\nv\r\n", "+OK\r\n"),
+            ("*2\r\n$3\r\nttl\r\n$1\r\nk\r\n", ":-1\r\n"),
+            ("*2\r\n$4\r\npttl\r\n$1\r\nn\r\n", ":-2\r\n"),
+            ("*2\r\n$10\r\nexpiretime\r\n$1\r\nk\r\n", ":-1\r\n"),
+            ("*2\r\n$11\r\npexpiretime\r\n$1\r\nn\r\n", ":-2\r\n"),
+            ("*3\r\n$6\r\nexpire\r\n$1\r\nn\r\n$2\r\n10\r\n", ":0\r\n"),
+            ("*4\r\n$6\r\nexpire\r\n$1\r\nk\r\n$2\r\n10\r\n$2\r\ngt\r\n", ":0\r\n"),
+            ("*4\r\n$6\r\nexpire\r\n$1\r\nk\r\n$2\r\n10\r\n$2\r\nxx\r\n", ":0\r\n"),
+            ("*4\r\n$9\r\npexpireat\r\n$1\r\nk\r\n$15\r\n100000000000000\r\n$2\r\nnx\r\n", ":1\r\n"),
+            ("*2\r\n$11\r\npexpiretime\r\n$1\r\nk\r\n", ":100000000000000\r\n"),
+            ("*2\r\n$10\r\nexpiretime\r\n$1\r\nk\r\n", ":100000000000\r\n"),
+            ("*4\r\n$8\r\nexpireat\r\n$1\r\nk\r\n$12\r\n200000000000\r\n$2\r\nnx\r\n", ":0\r\n"),
+            ("*5\r\n$8\r\nexpireat\r\n$1\r\nk\r\n$12\r\n200000000000\r\n$2\r\nxx\r\n$2\r\ngt\r\n", ":1\r\n"),
+            ("*4\r\n$8\r\nexpireat\r\n$1\r\nk\r\n$12\r\n100000000000\r\n$2\r\ngt\r\n", ":0\r\n"),
+            ("*4\r\n$8\r\nexpireat\r\n$1\r\nk\r\n$12\r\n100000000000\r\n$2\r\nlt\r\n", ":1\r\n"),
+            ("*2\r\n$10\r\nexpiretime\r\n$1\r\nk\r\n", ":100000000000\r\n"),
+            ("*3\r\n$6\r\nexpire\r\n$1\r\nk\r\n$3\r\n100\r\n", ":1\r\n"),
+            ("*2\r\n$3\r\nttl\r\n$1\r\nk\r\n", ":100\r\n"),
+            ("*2\r\n$7\r\npersist\r\n$1\r\nk\r\n", ":1\r\n"),
+            ("*2\r\n$7\r\npersist\r\n$1\r\nk\r\n", ":0\r\n"),
+            ("*2\r\n$7\r\npersist\r\n$1\r\nn\r\n", ":0\r\n"),
+            ("*2\r\n$3\r\nttl\r\n$1\r\nk\r\n", ":-1\r\n"),
+            ("*4\r\n$7\r\npexpire\r\n$1\r\nk\r\n$6\r\n100000\r\n$2\r\nlt\r\n", ":1\r\n"),
+            ("*5\r\n$6\r\nexpire\r\n$1\r\nk\r\n$2\r\n10\r\n$2\r\nnx\r\n$2\r\nxx\r\n", "-ERR NX and XX, GT or LT options at the same time are not compatible\r\n"),
+            ("*5\r\n$6\r\nexpire\r\n$1\r\nk\r\n$2\r\n10\r\n$2\r\ngt\r\n$2\r\nlt\r\n", "-ERR GT and LT options at the same time are not compatible\r\n"),
+            ("*4\r\n$6\r\nexpire\r\n$1\r\nk\r\n$2\r\n10\r\n$3\r\nfoo\r\n", "-ERR Unsupported option foo\r\n"),
+            ("*3\r\n$6\r\nexpire\r\n$1\r\nk\r\n$3\r\nten\r\n", "-ERR value is not an integer or out of range\r\n"),
+            ("*3\r\n$6\r\nexpire\r\n$1\r\nk\r\n$19\r\n9223372036854775807\r\n", "-ERR invalid expire time in 'expire' command\r\n"),
+            ("*3\r\n$6\r\nexpire\r\n$1\r\nk\r\n$2\r\n-1\r\n", ":1\r\n"),
+            ("*2\r\n$6\r\nexists\r\n$1\r\nk\r\n", ":0\r\n"),
+        ];
+
+        for test in tests {
+            let response = execute_command(test.0.as_bytes().to_vec(), &server);
+            assert_eq!(String::from_utf8_lossy(&response[..]), test.1, "{}", test.0);
+        }
+    }
+
+    #[test]
+    fn it_should_list_keys_matching_a_pattern() {
+        let server = Server::new(Config::default());
+        let tests = [
+            ("*2\r\n$4\r\nkeys\r\n$1\r\n*\r\n", "*0\r\n"),
+            ("*9\r\n$4\r\nmset\r\n$5\r\nhello\r\n$1\r\n1\r\n$5\r\nhallo\r\n$1\r\n2\r\n$5\r\nhxllo\r\n$1\r\n3\r\n$8\r\nheeeello\r\n$1\r\n4\r\n", "+OK\r\n"),
+            ("*5\r\n$3\r\nset\r\n$5\r\nh*llo\r\n$1\r\n5\r\n$4\r\npxat\r\n$1\r\n1\r\n", "+OK\r\n"),
+            ("*2\r\n$4\r\nkeys\r\n$5\r\nh?llo\r\n", "*3\r\n"),
+            ("*2\r\n$4\r\nkeys\r\n$5\r\nh*llo\r\n", "*4\r\n"),
+            ("*2\r\n$4\r\nkeys\r\n$8\r\nh[ae]llo\r\n", "*2\r\n"),
+            ("*2\r\n$4\r\nkeys\r\n$8\r\nh[^e]llo\r\n", "*2\r\n"),
+            ("*2\r\n$4\r\nkeys\r\n$9\r\nh[a-b]llo\r\n", "*1\r\n$5\r\nhallo\r\n"),
+            ("*2\r\n$4\r\nkeys\r\n$6\r\nh\\*llo\r\n", "*0\r\n"),
+            ("*4\r\n$4\r\nscan\r\n$1\r\n0\r\n$4\r\ntype\r\n$4\r\nlist\r\n", "*2\r\n$1\r\n0\r\n*0\r\n"),
+            ("*4\r\n$4\r\nscan\r\n$1\r\n0\r\n$5\r\ncount\r\n$1\r\n0\r\n", "-ERR syntax error\r\n"),
+            ("*3\r\n$4\r\nscan\r\n$1\r\n0\r\n$5\r\ncount\r\n", "-ERR syntax error\r\n"),
+            ("*4\r\n$4\r\nscan\r\n$1\r\n0\r\n$5\r\ncount\r\n$1\r\nx\r\n", "-ERR value is not an integer or out of range\r\n"),
+            ("*2\r\n$4\r\nscan\r\n$2\r\n-1\r\n", "-ERR invalid cursor\r\n"),
+            ("*4\r\n$4\r\nscan\r\n$1\r\n0\r\n$4\r\ntype\r\n$5\r\nbogus\r\n", "-ERR unknown type name 'bogus'\r\n"),
+        ];
+
+        // keys come back in no particular order, so most replies are only
+        // checked up to their length
+        for test in tests {
+            let response = execute_command(test.0.as_bytes().to_vec(), &server);
+            let response = String::from_utf8_lossy(&response[..]);
+            assert!(response.starts_with(test.1), "{} {}", test.0, response);
+        }
+    }
+
+    #[test]
+    fn it_should_scan_every_key() {
+        let server = Server::new(Config::default());
+        for i in 0..100 {
+            let key = format!("key:{}", i);
+            let command = format!(
+                "*3\r\n$3\r\nset\r\n${}\r\n{}\r\n$1\r\nv\r\n",
+                key.len(),
+                key
+            );
+            execute_command(command.into_bytes(), &server);
+        }
+
+        let mut keys = vec![];
+        let mut cursor = "0".to_string();
+        loop {
+            let command = format!(
+                "*6\r\n$4\r\nscan\r\n${}\r\n{}\r\n$5\r\nmatch\r\n$6\r\nkey:1*\r\n$5\r\ncount\r\n$1\r\n5\r\n",
+                cursor.len(),
+                cursor
+            );
+            let response = execute_command(command.into_bytes(), &server);
+            let reply = RESPDecoder::new(response).next_resp().unwrap().unwrap();
+            cursor = reply.array()[0].string();
+            keys.extend(reply.array()[1].array().iter().map(|key| key.string()));
+            if cursor == "0" {
+                break;
+            }
+        }
+
+        keys.sort();
+        keys.dedup();
+        let mut expected = vec!["key:1".to_string()];
+        expected.extend((10..20).map(|i| format!("key:{}", i)));
+        expected.sort();
+        assert_eq!(keys, expected);
+    }
+
     #[test]
     fn it_should_return_an_argument_error() {
         let tests = [
             ("echo", "*1\r\n$4\r\necho\r\n"),
             ("set", "*2\r\n$3\r\nset\r\n$3\r\nkey\r\n"),
             ("get", "*1\r\n$3\r\nget\r\n"),
+            ("ping", "*3\r\n$4\r\nping\r\n$1\r\na\r\n$1\r\nb\r\n"),
+            ("config", "*1\r\n$6\r\nconfig\r\n"),
+            (
+                "command|count",
+                "*3\r\n$7\r\ncommand\r\n$5\r\ncount\r\n$1\r\na\r\n",
+            ),
         ];
 
         for test in tests {
-            let store = RwLock::new(Store::new());
-            let response = execute_command(test.1.as_bytes().to_vec(), &store);
+            let server = Server::new(Config::default());
+            let response = execute_command(test.1.as_bytes().to_vec(), &server);
             assert_eq!(
                 String::from_utf8_lossy(&response[..]),
                 format!(
@@ -260,10 +2107,341 @@ mod tests {
         }
     }
 
-    fn execute_command(command: Vec<u8>, store: &RwLock<Store>) -> Vec<u8> {
+    #[test]
+    fn it_should_reply_with_redis_errors_for_bad_input() {
+        let tests = [
+            (
+                "*5\r\n$3\r\nset\r\n$1\r\nk\r\n$1\r\nv\r\n$2\r\npx\r\n$3\r\nabc\r\n",
+                "-ERR value is not an integer or out of range\r\n",
+            ),
+            (
+                "*4\r\n$3\r\nset\r\n$1\r\nk\r\n$1\r\nv\r\n$2\r\npx\r\n",
+                "-ERR syntax error\r\n",
+            ),
+            (
+                "*4\r\n$3\r\nset\r\n$1\r\nk\r\n$1\r\nv\r\n$3\r\nfoo\r\n",
+                "-ERR syntax error\r\n",
+            ),
+            (
+                "*5\r\n$3\r\nset\r\n$1\r\nk\r\n$1\r\nv\r\n$2\r\npx\r\n$1\r\n0\r\n",
+                "-ERR invalid expire time in 'set' command\r\n",
+            ),
+            (
+                "*3\r\n$3\r\nfoo\r\n$1\r\na\r\n$4\r\nb\r\nc\r\n",
+                "-ERR unknown command 'foo', with args beginning with: 'a' 'b  c' \r\n",
+            ),
+            (
+                "*1\r\n$3\r\nfoo\r\n",
+                "-ERR unknown command 'foo', with args beginning with: \r\n",
+            ),
+        ];
+
+        for test in tests {
+            let server = Server::new(Config::default());
+            let response = execute_command(test.0.as_bytes().to_vec(), &server);
+            assert_eq!(String::from_utf8_lossy(&response[..]), test.1);
+        }
+    }
+
+    #[test]
+    fn it_should_cut_an_unknown_command_at_128_bytes() {
+        // 201 bytes, so the cut splits the 64th 'é'
+        let name = format!("a{}", "é".repeat(100));
+        let command = format!("*2\r\n${}\r\n{}\r\n$2\r\né\r\n", name.len(), name);
+        let server = Server::new(Config::default());
+        let response = execute_command(command.into_bytes(), &server);
+        assert_eq!(
+            String::from_utf8_lossy(&response[..]),
+            format!(
+                "-ERR unknown command 'a{}\u{FFFD}', with args beginning with: 'é' \r\n",
+                "é".repeat(63)
+            )
+        );
+    }
+
+    #[test]
+    fn it_should_keep_line_breaks_out_of_protocol_errors() {
+        let error = DecodeError::Protocol("expected '$', got '\n'".to_string());
+        assert_eq!(
+            sanitize_error_message(&error.to_string()),
+            "Protocol error: expected '$', got ' '"
+        );
+    }
+
+    #[test]
+    fn it_should_switch_protocol_with_hello() {
+        let server = Server::new(Config::default());
+        let mut client = Client::new();
+
+        let hello_command = b"*2\r\n$5\r\nhello\r\n$1\r\n3\r\n";
+        let response = execute_command_with_client(hello_command.to_vec(), &server, &mut client);
+        assert_eq!(client.protocol, ProtocolVersion::RESP3);
+
+        let resp = RESPDecoder::new(response).next_resp().unwrap().unwrap();
+        assert_eq!(resp.value_type, Type::Map);
+        let pairs = resp.pairs();
+        assert_eq!(pairs[0].0.string(), "server");
+        assert_eq!(pairs[0].1.string(), "redis");
+        assert_eq!(pairs[2].0.string(), "proto");
+        assert_eq!(pairs[2].1.integer(), Some(3));
+
+        let get_command = b"*2\r\n$3\r\nget\r\n$7\r\nmissing\r\n";
+        let response = execute_command_with_client(get_command.to_vec(), &server, &mut client);
+        assert_eq!(String::from_utf8_lossy(&response[..]), RESP3_NULL);
+    }
+
+    #[test]
+    fn it_should_reply_hello_as_flat_array_in_resp2() {
+        let server = Server::new(Config::default());
+        let mut client = Client::new();
+        let hello_command = b"*4\r\n$5\r\nhello\r\n$1\r\n2\r\n$7\r\nsetname\r\n$3\r\napp\r\n";
+        let response = execute_command_with_client(hello_command.to_vec(), &server, &mut client);
+
+        let resp = RESPDecoder::new(response).next_resp().unwrap().unwrap();
+        assert_eq!(resp.value_type, Type::Array);
+        assert_eq!(resp.array().len(), 14);
+        assert_eq!(client.protocol, ProtocolVersion::RESP2);
+        assert_eq!(&client.name.unwrap()[..], b"app");
+    }
+
+    #[test]
+    fn it_should_reject_unknown_hello_arguments() {
+        let tests = [
+            (
+                "*2\r\n$5\r\nhello\r\n$1\r\n4\r\n",
+                "-NOPROTO unsupported protocol version\r\n",
+            ),
+            (
+                "*2\r\n$5\r\nhello\r\n$3\r\nabc\r\n",
+                "-ERR Protocol version is not an integer or out of range\r\n",
+            ),
+            (
+                "*3\r\n$5\r\nhello\r\n$1\r\n3\r\n$3\r\nfoo\r\n",
+                "-ERR Syntax error in HELLO option 'foo'\r\n",
+            ),
+        ];
+
+        for test in tests {
+            let server = Server::new(Config::default());
+            let mut client = Client::new();
+            let response =
+                execute_command_with_client(test.0.as_bytes().to_vec(), &server, &mut client);
+            assert_eq!(String::from_utf8_lossy(&response[..]), test.1);
+            assert_eq!(client.protocol, ProtocolVersion::RESP2);
+        }
+    }
+
+    #[test]
+    fn it_should_get_config_parameters_by_glob() {
+        let server = Server::new(Config::default());
+        let command = b"*3\r\n$6\r\nCONFIG\r\n$3\r\nGET\r\n$4\r\nMAX*\r\n";
+        let response = execute_command(command.to_vec(), &server);
+        assert_eq!(
+            String::from_utf8_lossy(&response[..]),
+            "*2\r\n$10\r\nmaxclients\r\n$5\r\n10000\r\n"
+        );
+
+        let command = b"*4\r\n$6\r\nconfig\r\n$3\r\nget\r\n$4\r\nport\r\n$7\r\ntimeout\r\n";
+        let response = execute_command(command.to_vec(), &server);
+        let resp = RESPDecoder::new(response).next_resp().unwrap().unwrap();
+        assert_eq!(resp.array().len(), 4);
+    }
+
+    #[test]
+    fn it_should_set_config_parameters_atomically() {
+        let server = Server::new(Config::default());
+        let command = b"*6\r\n$6\r\nconfig\r\n$3\r\nset\r\n$7\r\ntimeout\r\n$2\r\n30\r\n$10\r\nmaxclients\r\n$3\r\nabc\r\n";
+        let response = execute_command(command.to_vec(), &server);
+        assert_eq!(
+            String::from_utf8_lossy(&response[..]),
+            "-ERR CONFIG SET failed (possibly related to argument 'maxclients') - argument couldn't be parsed into an integer\r\n"
+        );
+        assert_eq!(server.config.read().unwrap().timeout, 0);
+
+        let command = b"*6\r\n$6\r\nconfig\r\n$3\r\nset\r\n$7\r\ntimeout\r\n$2\r\n30\r\n$10\r\nmaxclients\r\n$2\r\n50\r\n";
+        let response = execute_command(command.to_vec(), &server);
+        assert_eq!(String::from_utf8_lossy(&response[..]), "+OK\r\n");
+        assert_eq!(server.config.read().unwrap().timeout, 30);
+        assert_eq!(server.config.read().unwrap().maxclients, 50);
+    }
+
+    #[test]
+    fn it_should_reject_invalid_config_set_arguments() {
+        let tests = [
+            (
+                "*4\r\n$6\r\nconfig\r\n$3\r\nset\r\n$3\r\nfoo\r\n$3\r\nbar\r\n",
+                "-ERR Unknown option or number of arguments for CONFIG SET - 'foo'\r\n",
+            ),
+            (
+                "*4\r\n$6\r\nconfig\r\n$3\r\nset\r\n$4\r\nport\r\n$4\r\n6380\r\n",
+                "-ERR CONFIG SET failed (possibly related to argument 'port') - can't set immutable config\r\n",
+            ),
+            (
+                "*6\r\n$6\r\nconfig\r\n$3\r\nset\r\n$7\r\ntimeout\r\n$1\r\n1\r\n$7\r\nTIMEOUT\r\n$1\r\n2\r\n",
+                "-ERR Duplicate parameter - timeout\r\n",
+            ),
+            (
+                "*3\r\n$6\r\nconfig\r\n$3\r\nset\r\n$7\r\ntimeout\r\n",
+                "-ERR wrong number of arguments for 'config|set' command\r\n",
+            ),
+            (
+                "*2\r\n$6\r\nconfig\r\n$3\r\nfoo\r\n",
+                "-ERR unknown subcommand 'foo'. Try CONFIG HELP.\r\n",
+            ),
+            (
+                "*2\r\n$6\r\nconfig\r\n$7\r\nrewrite\r\n",
+                "-ERR The server is running without a config file\r\n",
+            ),
+        ];
+
+        for test in tests {
+            let server = Server::new(Config::default());
+            let response = execute_command(test.0.as_bytes().to_vec(), &server);
+            assert_eq!(String::from_utf8_lossy(&response[..]), test.1);
+        }
+    }
+
+    #[test]
+    fn it_should_reset_stats() {
+        let clock = Arc::new(ManualClock::new(START_TIME));
+        let server = Server::with_clock(Config::default(), clock.clone());
+        let set_command = b"*5\r\n$3\r\nset\r\n$3\r\nkey\r\n$1\r\n1\r\n$2\r\npx\r\n$3\r\n100\r\n";
+        execute_command(set_command.to_vec(), &server);
+        clock.advance(Duration::from_millis(101));
+        let get_command = b"*2\r\n$3\r\nget\r\n$3\r\nkey\r\n";
+        execute_command(get_command.to_vec(), &server);
+        assert_eq!(server.stats.keyspace_misses.load(Ordering::Relaxed), 1);
+        assert_eq!(server.stats.expired_keys.load(Ordering::Relaxed), 1);
+
+        let command = b"*2\r\n$6\r\nconfig\r\n$9\r\nresetstat\r\n";
+        let response = execute_command(command.to_vec(), &server);
+        assert_eq!(String::from_utf8_lossy(&response[..]), "+OK\r\n");
+        assert_eq!(server.stats.keyspace_misses.load(Ordering::Relaxed), 0);
+        assert_eq!(server.stats.expired_keys.load(Ordering::Relaxed), 0);
+        assert_eq!(
+            server
+                .stats
+                .total_commands_processed
+                .load(Ordering::Relaxed),
+            0
+        );
+    }
+
+    #[test]
+    fn it_should_shut_down_without_replying() {
+        let tests = [
+            (
+                "*2\r\n$8\r\nshutdown\r\n$3\r\nfoo\r\n",
+                "-ERR syntax error\r\n",
+            ),
+            (
+                "*3\r\n$8\r\nshutdown\r\n$4\r\nsave\r\n$6\r\nnosave\r\n",
+                "-ERR syntax error\r\n",
+            ),
+            (
+                "*3\r\n$8\r\nshutdown\r\n$5\r\nabort\r\n$3\r\nnow\r\n",
+                "-ERR syntax error\r\n",
+            ),
+            (
+                "*2\r\n$8\r\nshutdown\r\n$5\r\nABORT\r\n",
+                "-ERR No shutdown in progress.\r\n",
+            ),
+        ];
+        let server = Server::new(Config::default());
+        for test in tests {
+            let response = execute_command(test.0.as_bytes().to_vec(), &server);
+            assert_eq!(String::from_utf8_lossy(&response[..]), test.1);
+        }
+        assert!(!server.is_shutting_down());
+
+        let mut client = Client::new();
+        let command = b"*3\r\n$8\r\nshutdown\r\n$6\r\nnosave\r\n$3\r\nnow\r\n";
+        let response = execute_command_with_client(command.to_vec(), &server, &mut client);
+        assert!(response.is_empty());
+        assert!(client.closing);
+        assert!(server.is_shutting_down());
+    }
+
+    #[test]
+    fn it_should_count_and_describe_commands() {
+        let server = Server::new(Config::default());
+        let command = b"*2\r\n$7\r\ncommand\r\n$5\r\ncount\r\n";
+        let response = execute_command(command.to_vec(), &server);
+        assert_eq!(
+            String::from_utf8_lossy(&response[..]),
+            format!(":{}\r\n", COMMAND_TABLE.len())
+        );
+
+        let command = b"*4\r\n$7\r\ncommand\r\n$4\r\ninfo\r\n$3\r\nGET\r\n$7\r\nmissing\r\n";
+        let response = execute_command(command.to_vec(), &server);
+        let resp = RESPDecoder::new(response).next_resp().unwrap().unwrap();
+        let entries = resp.array();
+        assert_eq!(entries.len(), 2);
+        let get = entries[0].array();
+        assert_eq!(get[0].string(), "get");
+        assert_eq!(get[1].integer(), Some(2));
+        assert_eq!(
+            get[2]
+                .array()
+                .iter()
+                .map(|flag| flag.string())
+                .collect::<Vec<_>>(),
+            vec!["readonly", "fast"]
+        );
+        assert_eq!(
+            (get[3].integer(), get[4].integer(), get[5].integer()),
+            (Some(1), Some(1), Some(1))
+        );
+        assert!(entries[1].is_null_array());
+
+        let command = b"*3\r\n$7\r\ncommand\r\n$4\r\ndocs\r\n$10\r\nconfig|set\r\n";
+        let response = execute_command(command.to_vec(), &server);
+        let resp = RESPDecoder::new(response).next_resp().unwrap().unwrap();
+        let docs = resp.array();
+        assert_eq!(docs[0].string(), "config|set");
+        assert_eq!(docs[1].array()[0].string(), "summary");
+    }
+
+    #[test]
+    fn it_should_get_keys_of_a_command() {
+        let tests = [
+            (
+                "*5\r\n$7\r\ncommand\r\n$7\r\ngetkeys\r\n$3\r\nset\r\n$3\r\nkey\r\n$5\r\nvalue\r\n",
+                "*1\r\n$3\r\nkey\r\n",
+            ),
+            (
+                "*4\r\n$7\r\ncommand\r\n$7\r\ngetkeys\r\n$3\r\nfoo\r\n$3\r\nkey\r\n",
+                "-ERR Invalid command specified\r\n",
+            ),
+            (
+                "*5\r\n$7\r\ncommand\r\n$7\r\ngetkeys\r\n$3\r\nget\r\n$1\r\na\r\n$1\r\nb\r\n",
+                "-ERR Invalid number of arguments specified for command\r\n",
+            ),
+            (
+                "*4\r\n$7\r\ncommand\r\n$7\r\ngetkeys\r\n$4\r\necho\r\n$1\r\na\r\n",
+                "-ERR The command has no key arguments\r\n",
+            ),
+        ];
+
+        for test in tests {
+            let server = Server::new(Config::default());
+            let response = execute_command(test.0.as_bytes().to_vec(), &server);
+            assert_eq!(String::from_utf8_lossy(&response[..]), test.1);
+        }
+    }
+
+    fn execute_command(command: Vec<u8>, server: &Server) -> Vec<u8> {
+        execute_command_with_client(command, server, &mut Client::new())
+    }
+
+    fn execute_command_with_client(
+        command: Vec<u8>,
+        server: &Server,
+        client: &mut Client,
+    ) -> Vec<u8> {
         let mut decorder = RESPDecoder::new(command);
-        let resp = decorder.next_resp().unwrap();
+        let resp = decorder.next_resp().unwrap().unwrap();
         let args = resp.array();
-        CommandExecutor::new(args, store).execute()
+        CommandExecutor::new(args, server, client).execute()
     }
 }
@@ -0,0 +1,1145 @@
+use crate::command_executor::{CommandExecutor, CommandResult};
+use crate::resp::RESP;
+
+pub type Handler = fn(&mut CommandExecutor) -> CommandResult;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CommandFlag {
+    Write,
+    ReadOnly,
+    DenyOom,
+    Admin,
+    NoScript,
+    Blocking,
+    Loading,
+    Stale,
+    Fast,
+    NoAuth,
+    AllowBusy,
+}
+
+impl CommandFlag {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CommandFlag::Write => "write",
+            CommandFlag::ReadOnly => "readonly",
+            CommandFlag::DenyOom => "denyoom",
+            CommandFlag::Admin => "admin",
+            CommandFlag::NoScript => "noscript",
+            CommandFlag::Blocking => "blocking",
+            CommandFlag::Loading => "loading",
+            CommandFlag::Stale => "stale",
+            CommandFlag::Fast => "fast",
+            CommandFlag::NoAuth => "no_auth",
+            CommandFlag::AllowBusy => "allow_busy",
+        }
+    }
+}
+
+/// An entry of the command table.
+///
+/// `arity` counts the command name itself; a negative arity means "at least".
+/// Keys are the arguments from `first_key` to `last_key` every `step`
+/// arguments, where a negative `last_key` counts from the end and a
+/// `first_key` of 0 means the command takes no keys.
+pub struct Command {
+    pub name: &'static str,
+    pub arity: i64,
+    pub flags: &'static [CommandFlag],
+    pub first_key: i64,
+    pub last_key: i64,
+    pub step: i64,
+    pub group: &'static str,
+    pub summary: &'static str,
+    pub since: &'static str,
+    pub complexity: &'static str,
+    /// `None` for containers such as CONFIG that only run subcommands.
+    pub handler: Option<Handler>,
+    pub subcommands: &'static [Command],
+}
+
+impl Command {
+    pub fn has_valid_arity(&self, argc: usize) -> bool {
+        let argc = argc as i64;
+        if self.arity >= 0 {
+            argc == self.arity
+        } else {
+            argc >= -self.arity
+        }
+    }
+
+    pub fn has_flag(&self, flag: CommandFlag) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    /// Looks up a subcommand by its own name, e.g. `get` for `config|get`.
+    pub fn subcommand(&self, name: &str) -> Option<&'static Command> {
+        let name = name.to_lowercase();
+        self.subcommands
+            .iter()
+            .find(|subcommand| subcommand.name.split('|').nth(1) == Some(&*name))
+    }
+
+    /// Returns the positions of the keys in a call with `argc` arguments.
+    pub fn key_positions(&self, argc: usize) -> Vec<usize> {
+        if self.first_key <= 0 {
+            return vec![];
+        }
+
+        let last_key = if self.last_key < 0 {
+            argc as i64 + self.last_key
+        } else {
+            self.last_key.min(argc as i64 - 1)
+        };
+        (self.first_key..=last_key)
+            .step_by(self.step.max(1) as usize)
+            .map(|position| position as usize)
+            .collect()
+    }
+
+    pub fn acl_categories(&self) -> Vec<&'static str> {
+        let mut categories = vec![];
+        if self.has_flag(CommandFlag::Write) {
+            categories.push("@write");
+        }
+        if self.has_flag(CommandFlag::ReadOnly) {
+            categories.push("@read");
+        }
+        if self.has_flag(CommandFlag::Admin) {
+            categories.push("@admin");
+            categories.push("@dangerous");
+        }
+        match self.group {
+            "string" => categories.push("@string"),
+            "generic" => categories.push("@keyspace"),
+            "connection" => categories.push("@connection"),
+            _ => {}
+        }
+        if self.has_flag(CommandFlag::Fast) {
+            categories.push("@fast");
+        } else {
+            categories.push("@slow");
+        }
+        categories
+    }
+
+    /// The entry `COMMAND INFO` returns for this command.
+    pub fn info(&self) -> RESP {
+        RESP::new_array(vec![
+            RESP::new_bulk_string(self.name),
+            RESP::new_integer(self.arity),
+            RESP::new_set(
+                self.flags
+                    .iter()
+                    .map(|flag| RESP::new_simple_string(flag.name()))
+                    .collect(),
+            ),
+            RESP::new_integer(self.first_key),
+            RESP::new_integer(self.last_key),
+            RESP::new_integer(self.step),
+            RESP::new_set(
+                self.acl_categories()
+                    .into_iter()
+                    .map(RESP::new_simple_string)
+                    .collect(),
+            ),
+            RESP::new_array(vec![]),
+            RESP::new_array(self.key_specs()),
+            RESP::new_array(
+                self.subcommands
+                    .iter()
+                    .map(|subcommand| subcommand.info())
+                    .collect(),
+            ),
+        ])
+    }
+
+    /// The entry `COMMAND DOCS` returns for this command.
+    pub fn docs(&self) -> RESP {
+        let mut pairs = vec![
+            (
+                RESP::new_bulk_string("summary"),
+                RESP::new_simple_string(self.summary),
+            ),
+            (
+                RESP::new_bulk_string("since"),
+                RESP::new_simple_string(self.since),
+            ),
+            (
+                RESP::new_bulk_string("group"),
+                RESP::new_simple_string(self.group),
+            ),
+            (
+                RESP::new_bulk_string("complexity"),
+                RESP::new_simple_string(self.complexity),
+            ),
+        ];
+        if !self.subcommands.is_empty() {
+            pairs.push((
+                RESP::new_bulk_string("subcommands"),
+                RESP::new_map(
+                    self.subcommands
+                        .iter()
+                        .map(|subcommand| {
+                            (RESP::new_bulk_string(subcommand.name), subcommand.docs())
+                        })
+                        .collect(),
+                ),
+            ));
+        }
+        RESP::new_map(pairs)
+    }
+
+    /// Describes the key range in the Redis 7 key specification format.
+    fn key_specs(&self) -> Vec<RESP> {
+        if self.first_key <= 0 {
+            return vec![];
+        }
+
+        let access = if self.has_flag(CommandFlag::Write) {
+            "RW"
+        } else {
+            "RO"
+        };
+        // the spec counts the last key relative to the first one
+        let last_key = if self.last_key < 0 {
+            self.last_key
+        } else {
+            self.last_key - self.first_key
+        };
+        vec![RESP::new_map(vec![
+            (
+                RESP::new_bulk_string("flags"),
+                RESP::new_set(vec![RESP::new_simple_string(access)]),
+            ),
+            (
+                RESP::new_bulk_string("begin_search"),
+                RESP::new_map(vec![
+                    (
+                        RESP::new_bulk_string("type"),
+                        RESP::new_bulk_string("index"),
+                    ),
+                    (
+                        RESP::new_bulk_string("spec"),
+                        RESP::new_map(vec![(
+                            RESP::new_bulk_string("index"),
+                            RESP::new_integer(self.first_key),
+                        )]),
+                    ),
+                ]),
+            ),
+            (
+                RESP::new_bulk_string("find_keys"),
+                RESP::new_map(vec![
+                    (
+                        RESP::new_bulk_string("type"),
+                        RESP::new_bulk_string("range"),
+                    ),
+                    (
+                        RESP::new_bulk_string("spec"),
+                        RESP::new_map(vec![
+                            (
+                                RESP::new_bulk_string("lastkey"),
+                                RESP::new_integer(last_key),
+                            ),
+                            (
+                                RESP::new_bulk_string("keystep"),
+                                RESP::new_integer(self.step),
+                            ),
+                            (RESP::new_bulk_string("limit"), RESP::new_integer(0)),
+                        ]),
+                    ),
+                ]),
+            ),
+        ])]
+    }
+}
+
+pub fn lookup(name: &str) -> Option<&'static Command> {
+    let name = name.to_lowercase();
+    COMMAND_TABLE.iter().find(|command| command.name == name)
+}
+
+pub static COMMAND_TABLE: &[Command] = &[
+    Command {
+        name: "ping",
+        arity: -1,
+        flags: &[CommandFlag::Fast],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "connection",
+        summary: "Returns the server's liveliness response.",
+        since: "1.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_ping_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "echo",
+        arity: 2,
+        flags: &[CommandFlag::Fast],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "connection",
+        summary: "Returns the given string.",
+        since: "1.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_echo_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "hello",
+        arity: -1,
+        flags: &[
+            CommandFlag::NoScript,
+            CommandFlag::Loading,
+            CommandFlag::Stale,
+            CommandFlag::Fast,
+            CommandFlag::NoAuth,
+            CommandFlag::AllowBusy,
+        ],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "connection",
+        summary: "Handshakes with the Redis server.",
+        since: "6.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_hello_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "get",
+        arity: 2,
+        flags: &[CommandFlag::ReadOnly, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Returns the string value of a key.",
+        since: "1.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_get_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "set",
+        arity: -3,
+        flags: &[CommandFlag::Write, CommandFlag::DenyOom],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist.",
+        since: "1.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_set_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "getdel",
+        arity: 2,
+        flags: &[CommandFlag::Write, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Returns the string value of a key after deleting the key.",
+        since: "6.2.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_getdel_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "getex",
+        arity: -2,
+        flags: &[CommandFlag::Write, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Returns the string value of a key after setting its expiration time.",
+        since: "6.2.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_getex_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "getset",
+        arity: 3,
+        flags: &[CommandFlag::Write, CommandFlag::DenyOom, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Returns the previous string value of a key after setting it to a new value.",
+        since: "1.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_getset_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "setnx",
+        arity: 3,
+        flags: &[CommandFlag::Write, CommandFlag::DenyOom, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Set the string value of a key only when the key doesn't exist.",
+        since: "1.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_setnx_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "setex",
+        arity: 4,
+        flags: &[CommandFlag::Write, CommandFlag::DenyOom],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Sets the string value and expiration time of a key. Creates the key if it doesn't exist.",
+        since: "2.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_setex_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "psetex",
+        arity: 4,
+        flags: &[CommandFlag::Write, CommandFlag::DenyOom],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Sets both string value and expiration time in milliseconds of a key. The key is created if it doesn't exist.",
+        since: "2.6.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_psetex_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "incr",
+        arity: 2,
+        flags: &[CommandFlag::Write, CommandFlag::DenyOom, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+        since: "1.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_incr_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "decr",
+        arity: 2,
+        flags: &[CommandFlag::Write, CommandFlag::DenyOom, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Decrements the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+        since: "1.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_decr_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "incrby",
+        arity: 3,
+        flags: &[CommandFlag::Write, CommandFlag::DenyOom, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Increments the integer value of a key by a number. Uses 0 as initial value if the key doesn't exist.",
+        since: "1.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_incrby_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "decrby",
+        arity: 3,
+        flags: &[CommandFlag::Write, CommandFlag::DenyOom, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Decrements a number from the integer value of a key. Uses 0 as initial value if the key doesn't exist.",
+        since: "1.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_decrby_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "incrbyfloat",
+        arity: 3,
+        flags: &[CommandFlag::Write, CommandFlag::DenyOom, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Increment the floating point value of a key by a number. Uses 0 as initial value if the key doesn't exist.",
+        since: "2.6.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_incrbyfloat_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "append",
+        arity: 3,
+        flags: &[CommandFlag::Write, CommandFlag::DenyOom],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Appends a string to the value of a key. Creates the key if it doesn't exist.",
+        since: "2.0.0",
+        complexity: "O(1). The amortized time complexity is O(1) assuming the appended value is small and the already present value is of any size, since the dynamic string library used by Redis will double the free space available on every reallocation.",
+        handler: Some(|executor| executor.execute_append_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "strlen",
+        arity: 2,
+        flags: &[CommandFlag::ReadOnly, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Returns the length of a string value.",
+        since: "2.2.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_strlen_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "getrange",
+        arity: 4,
+        flags: &[CommandFlag::ReadOnly],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Returns a substring of the string stored at a key.",
+        since: "2.4.0",
+        complexity: "O(N) where N is the length of the returned string. The complexity is ultimately determined by the returned length, but because creating a substring from an existing string is very cheap, it can be considered O(1) for small strings.",
+        handler: Some(|executor| executor.execute_getrange_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "substr",
+        arity: 4,
+        flags: &[CommandFlag::ReadOnly],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Returns a substring from a string value.",
+        since: "1.0.0",
+        complexity: "O(N) where N is the length of the returned string. The complexity is ultimately determined by the returned length, but because creating a substring from an existing string is very cheap, it can be considered O(1) for small strings.",
+        handler: Some(|executor| executor.execute_getrange_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "setrange",
+        arity: 4,
+        flags: &[CommandFlag::Write, CommandFlag::DenyOom],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Overwrites a part of a string value with another by an offset. Creates the key if it doesn't exist.",
+        since: "2.2.0",
+        complexity: "O(1), not counting the time taken to copy the new string in place. Usually, this string is very small so the amortized complexity is O(1). Otherwise, complexity is O(M) with M being the length of the value argument.",
+        handler: Some(|executor| executor.execute_setrange_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "lcs",
+        arity: -3,
+        flags: &[CommandFlag::ReadOnly],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        group: "string",
+        summary: "Finds the longest common substring.",
+        since: "7.0.0",
+        complexity: "O(N*M) where N and M are the lengths of s1 and s2, respectively",
+        handler: Some(|executor| executor.execute_lcs_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "mget",
+        arity: -2,
+        flags: &[CommandFlag::ReadOnly, CommandFlag::Fast],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        group: "string",
+        summary: "Atomically returns the string values of one or more keys.",
+        since: "1.0.0",
+        complexity: "O(N) where N is the number of keys to retrieve.",
+        handler: Some(|executor| executor.execute_mget_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "mset",
+        arity: -3,
+        flags: &[CommandFlag::Write, CommandFlag::DenyOom],
+        first_key: 1,
+        last_key: -1,
+        step: 2,
+        group: "string",
+        summary: "Atomically creates or modifies the string values of one or more keys.",
+        since: "1.0.1",
+        complexity: "O(N) where N is the number of keys to set.",
+        handler: Some(|executor| executor.execute_mset_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "msetnx",
+        arity: -3,
+        flags: &[CommandFlag::Write, CommandFlag::DenyOom],
+        first_key: 1,
+        last_key: -1,
+        step: 2,
+        group: "string",
+        summary: "Atomically modifies the string values of one or more keys only when all keys don't exist.",
+        since: "1.0.1",
+        complexity: "O(N) where N is the number of keys to set.",
+        handler: Some(|executor| executor.execute_msetnx_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "del",
+        arity: -2,
+        flags: &[CommandFlag::Write],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        group: "generic",
+        summary: "Deletes one or more keys.",
+        since: "1.0.0",
+        complexity: "O(N) where N is the number of keys that will be removed. When a key to remove holds a value other than a string, the individual complexity for this key is O(M) where M is the number of elements in the list, set, sorted set or hash. Removing a single key that holds a string value is O(1).",
+        handler: Some(|executor| executor.execute_del_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "unlink",
+        arity: -2,
+        flags: &[CommandFlag::Write, CommandFlag::Fast],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        group: "generic",
+        summary: "Asynchronously deletes one or more keys.",
+        since: "4.0.0",
+        complexity: "O(1) for each key removed regardless of its size. Then the command does O(N) work in a different thread in order to reclaim memory, where N is the number of allocations the deleted objects where composed of.",
+        handler: Some(|executor| executor.execute_del_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "exists",
+        arity: -2,
+        flags: &[CommandFlag::ReadOnly, CommandFlag::Fast],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        group: "generic",
+        summary: "Determines whether one or more keys exist.",
+        since: "1.0.0",
+        complexity: "O(N) where N is the number of keys to check.",
+        handler: Some(|executor| executor.execute_exists_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "type",
+        arity: 2,
+        flags: &[CommandFlag::ReadOnly, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Determines the type of value stored at a key.",
+        since: "1.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_type_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "rename",
+        arity: 3,
+        flags: &[CommandFlag::Write],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        group: "generic",
+        summary: "Renames a key and overwrites the destination.",
+        since: "1.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_rename_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "renamenx",
+        arity: 3,
+        flags: &[CommandFlag::Write, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        group: "generic",
+        summary: "Renames a key only when the target key name doesn't exist.",
+        since: "1.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_renamenx_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "copy",
+        arity: -3,
+        flags: &[CommandFlag::Write, CommandFlag::DenyOom],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        group: "generic",
+        summary: "Copies the value of a key to a new key.",
+        since: "6.2.0",
+        complexity: "O(N) worst case for collections, where N is the number of nested items. O(1) for string values.",
+        handler: Some(|executor| executor.execute_copy_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "touch",
+        arity: -2,
+        flags: &[CommandFlag::ReadOnly, CommandFlag::Fast],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        group: "generic",
+        summary: "Returns the number of existing keys out of those specified after updating the time they were last accessed.",
+        since: "3.2.1",
+        complexity: "O(N) where N is the number of keys that will be touched.",
+        handler: Some(|executor| executor.execute_exists_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "expire",
+        arity: -3,
+        flags: &[CommandFlag::Write, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Sets the expiration time of a key in seconds.",
+        since: "1.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_expire_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "pexpire",
+        arity: -3,
+        flags: &[CommandFlag::Write, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Sets the expiration time of a key in milliseconds.",
+        since: "2.6.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_pexpire_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "expireat",
+        arity: -3,
+        flags: &[CommandFlag::Write, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Sets the expiration time of a key to a Unix timestamp.",
+        since: "1.2.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_expireat_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "pexpireat",
+        arity: -3,
+        flags: &[CommandFlag::Write, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Sets the expiration time of a key to a Unix milliseconds timestamp.",
+        since: "2.6.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_pexpireat_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "ttl",
+        arity: 2,
+        flags: &[CommandFlag::ReadOnly, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Returns the expiration time in seconds of a key.",
+        since: "1.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_ttl_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "pttl",
+        arity: 2,
+        flags: &[CommandFlag::ReadOnly, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Returns the expiration time in milliseconds of a key.",
+        since: "2.6.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_pttl_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "expiretime",
+        arity: 2,
+        flags: &[CommandFlag::ReadOnly, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Returns the expiration time of a key as a Unix timestamp.",
+        since: "7.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_expiretime_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "pexpiretime",
+        arity: 2,
+        flags: &[CommandFlag::ReadOnly, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Returns the expiration time of a key as a Unix milliseconds timestamp.",
+        since: "7.0.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_pexpiretime_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "persist",
+        arity: 2,
+        flags: &[CommandFlag::Write, CommandFlag::Fast],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Removes the expiration time of a key.",
+        since: "2.2.0",
+        complexity: "O(1)",
+        handler: Some(|executor| executor.execute_persist_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "keys",
+        arity: 2,
+        flags: &[CommandFlag::ReadOnly],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "generic",
+        summary: "Returns all key names that match a pattern.",
+        since: "1.0.0",
+        complexity: "O(N) with N being the number of keys in the database, under the assumption that the key names in the database and the given pattern have limited length.",
+        handler: Some(|executor| executor.execute_keys_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "scan",
+        arity: -2,
+        flags: &[CommandFlag::ReadOnly],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "generic",
+        summary: "Iterates over the key names in the database.",
+        since: "2.8.0",
+        complexity: "O(1) for every call. O(N) for a complete iteration, including enough command calls for the cursor to return back to 0. N is the number of elements inside the collection.",
+        handler: Some(|executor| executor.execute_scan_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "config",
+        arity: -2,
+        flags: &[],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "A container for server configuration commands.",
+        since: "2.0.0",
+        complexity: "Depends on subcommand.",
+        handler: None,
+        subcommands: &[
+            Command {
+                name: "config|get",
+                arity: -3,
+                flags: &[
+                    CommandFlag::Admin,
+                    CommandFlag::NoScript,
+                    CommandFlag::Loading,
+                    CommandFlag::Stale,
+                ],
+                first_key: 0,
+                last_key: 0,
+                step: 0,
+                group: "server",
+                summary: "Returns the effective values of configuration parameters.",
+                since: "2.0.0",
+                complexity: "O(N) when N is the number of configuration parameters provided",
+                handler: Some(|executor| executor.execute_config_get_command()),
+                subcommands: &[],
+            },
+            Command {
+                name: "config|set",
+                arity: -4,
+                flags: &[
+                    CommandFlag::Admin,
+                    CommandFlag::NoScript,
+                    CommandFlag::Loading,
+                    CommandFlag::Stale,
+                ],
+                first_key: 0,
+                last_key: 0,
+                step: 0,
+                group: "server",
+                summary: "Sets configuration parameters in-flight.",
+                since: "2.0.0",
+                complexity: "O(N) when N is the number of configuration parameters provided",
+                handler: Some(|executor| executor.execute_config_set_command()),
+                subcommands: &[],
+            },
+            Command {
+                name: "config|rewrite",
+                arity: 2,
+                flags: &[
+                    CommandFlag::Admin,
+                    CommandFlag::NoScript,
+                    CommandFlag::Loading,
+                    CommandFlag::Stale,
+                ],
+                first_key: 0,
+                last_key: 0,
+                step: 0,
+                group: "server",
+                summary: "Persists the effective configuration to file.",
+                since: "2.8.0",
+                complexity: "O(1)",
+                handler: Some(|executor| executor.execute_config_rewrite_command()),
+                subcommands: &[],
+            },
+            Command {
+                name: "config|resetstat",
+                arity: 2,
+                flags: &[
+                    CommandFlag::Admin,
+                    CommandFlag::NoScript,
+                    CommandFlag::Loading,
+                    CommandFlag::Stale,
+                ],
+                first_key: 0,
+                last_key: 0,
+                step: 0,
+                group: "server",
+                summary: "Resets the server's statistics.",
+                since: "2.0.0",
+                complexity: "O(1)",
+                handler: Some(|executor| executor.execute_config_resetstat_command()),
+                subcommands: &[],
+            },
+            Command {
+                name: "config|help",
+                arity: 2,
+                flags: &[CommandFlag::Loading, CommandFlag::Stale],
+                first_key: 0,
+                last_key: 0,
+                step: 0,
+                group: "server",
+                summary: "Returns helpful text about the different subcommands.",
+                since: "5.0.0",
+                complexity: "O(1)",
+                handler: Some(|executor| executor.execute_config_help_command()),
+                subcommands: &[],
+            },
+        ],
+    },
+    Command {
+        name: "shutdown",
+        arity: -1,
+        flags: &[
+            CommandFlag::Admin,
+            CommandFlag::NoScript,
+            CommandFlag::Loading,
+            CommandFlag::Stale,
+            CommandFlag::AllowBusy,
+        ],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "Synchronously saves the database(s) to disk and shuts down the Redis server.",
+        since: "1.0.0",
+        complexity: "O(N) when saving, where N is the total number of keys in all databases when saving data, otherwise O(1)",
+        handler: Some(|executor| executor.execute_shutdown_command()),
+        subcommands: &[],
+    },
+    Command {
+        name: "command",
+        arity: -1,
+        flags: &[CommandFlag::Loading, CommandFlag::Stale],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "Returns detailed information about all commands.",
+        since: "2.8.13",
+        complexity: "O(N) where N is the total number of Redis commands",
+        handler: Some(|executor| executor.execute_command_command()),
+        subcommands: &[
+            Command {
+                name: "command|count",
+                arity: 2,
+                flags: &[CommandFlag::Loading, CommandFlag::Stale],
+                first_key: 0,
+                last_key: 0,
+                step: 0,
+                group: "server",
+                summary: "Returns a count of commands.",
+                since: "2.8.13",
+                complexity: "O(1)",
+                handler: Some(|executor| executor.execute_command_count_command()),
+                subcommands: &[],
+            },
+            Command {
+                name: "command|info",
+                arity: -2,
+                flags: &[CommandFlag::Loading, CommandFlag::Stale],
+                first_key: 0,
+                last_key: 0,
+                step: 0,
+                group: "server",
+                summary: "Returns information about one, multiple or all commands.",
+                since: "2.8.13",
+                complexity: "O(N) where N is the number of commands to look up",
+                handler: Some(|executor| executor.execute_command_info_command()),
+                subcommands: &[],
+            },
+            Command {
+                name: "command|docs",
+                arity: -2,
+                flags: &[CommandFlag::Loading, CommandFlag::Stale],
+                first_key: 0,
+                last_key: 0,
+                step: 0,
+                group: "server",
+                summary: "Returns documentary information about one, multiple or all commands.",
+                since: "7.0.0",
+                complexity: "O(N) where N is the number of commands to look up",
+                handler: Some(|executor| executor.execute_command_docs_command()),
+                subcommands: &[],
+            },
+            Command {
+                name: "command|getkeys",
+                arity: -3,
+                flags: &[CommandFlag::Loading, CommandFlag::Stale],
+                first_key: 0,
+                last_key: 0,
+                step: 0,
+                group: "server",
+                summary: "Extracts the key names from an arbitrary command.",
+                since: "2.8.13",
+                complexity: "O(N) where N is the number of arguments to the command",
+                handler: Some(|executor| executor.execute_command_getkeys_command()),
+                subcommands: &[],
+            },
+            Command {
+                name: "command|help",
+                arity: 2,
+                flags: &[CommandFlag::Loading, CommandFlag::Stale],
+                first_key: 0,
+                last_key: 0,
+                step: 0,
+                group: "server",
+                summary: "Returns helpful text about the different subcommands.",
+                since: "5.0.0",
+                complexity: "O(1)",
+                handler: Some(|executor| executor.execute_command_help_command()),
+                subcommands: &[],
+            },
+        ],
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{lookup, CommandFlag, COMMAND_TABLE};
+
+    #[test]
+    fn it_should_check_arity() {
+        let get = lookup("GET").unwrap();
+        assert!(get.has_valid_arity(2));
+        assert!(!get.has_valid_arity(3));
+
+        let set = lookup("set").unwrap();
+        assert!(!set.has_valid_arity(2));
+        assert!(set.has_valid_arity(3));
+        assert!(set.has_valid_arity(6));
+    }
+
+    #[test]
+    fn it_should_find_subcommands() {
+        let config = lookup("config").unwrap();
+        assert_eq!(config.subcommand("GET").unwrap().name, "config|get");
+        assert!(config.subcommand("unknown").is_none());
+        assert!(lookup("config|get").is_none());
+    }
+
+    #[test]
+    fn it_should_compute_key_positions() {
+        assert_eq!(lookup("get").unwrap().key_positions(2), vec![1]);
+        assert_eq!(lookup("set").unwrap().key_positions(5), vec![1]);
+        assert!(lookup("ping").unwrap().key_positions(1).is_empty());
+    }
+
+    #[test]
+    fn it_should_declare_consistent_entries() {
+        for command in COMMAND_TABLE {
+            assert_eq!(command.name, command.name.to_lowercase());
+            assert!(
+                !(command.has_flag(CommandFlag::Write) && command.has_flag(CommandFlag::ReadOnly)),
+                "{} is both write and readonly",
+                command.name
+            );
+            assert!(command.handler.is_some() || !command.subcommands.is_empty());
+            for subcommand in command.subcommands {
+                assert!(subcommand.name.starts_with(&format!("{}|", command.name)));
+            }
+        }
+    }
+}
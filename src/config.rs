@@ -0,0 +1,591 @@
+use crate::command_executor::REDIS_VERSION;
+use crate::resp;
+use crate::resp_decoder::{split_inline_args, DecoderLimits};
+use anyhow::{anyhow, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Guards against `include` directives that include each other.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Parameters known to `CONFIG GET`, `CONFIG SET` and `CONFIG REWRITE`, in the
+/// order they are listed.
+pub const PARAMETERS: [&str; 11] = [
+    "bind",
+    "port",
+    "unixsocket",
+    "unixsocketperm",
+    "dir",
+    "dbfilename",
+    "timeout",
+    "maxclients",
+    "proto-max-bulk-len",
+    "proto-max-multibulk-len",
+    "client-query-buffer-limit",
+];
+
+/// Parameters that only take effect at startup.
+const IMMUTABLE_PARAMETERS: [&str; 4] = ["bind", "port", "unixsocket", "unixsocketperm"];
+
+/// Startup configuration, read from a redis.conf-style file and command-line
+/// flags. Flags are applied after the file, so they take precedence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub bind: Vec<String>,
+    /// TCP port, 0 to not listen on TCP at all.
+    pub port: u16,
+    /// Path of a Unix domain socket to accept connections on.
+    pub unixsocket: Option<PathBuf>,
+    /// Permission bits of the Unix socket file, 0 to keep the umask default.
+    pub unixsocketperm: u32,
+    pub dir: PathBuf,
+    pub dbfilename: String,
+    /// Seconds a client may stay idle before it is disconnected, 0 to disable.
+    pub timeout: u64,
+    pub maxclients: u64,
+    pub proto_max_bulk_len: u64,
+    /// Largest element count a request array may announce.
+    pub proto_max_multibulk_len: u64,
+    /// Largest number of bytes buffered for a frame that is not complete yet.
+    pub client_query_buffer_limit: u64,
+    pub config_file: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind: vec!["127.0.0.1".to_string()],
+            port: 6379,
+            unixsocket: None,
+            unixsocketperm: 0,
+            dir: PathBuf::from("."),
+            dbfilename: "dump.rdb".to_string(),
+            timeout: 0,
+            maxclients: 10000,
+            proto_max_bulk_len: 512 * 1024 * 1024,
+            proto_max_multibulk_len: 1024 * 1024,
+            client_query_buffer_limit: 1024 * 1024 * 1024,
+            config_file: None,
+        }
+    }
+}
+
+impl Config {
+    /// Builds the configuration from `[configfile] [--name value ...]`, the
+    /// same arguments `redis-server` takes.
+    pub fn from_args(args: &[String]) -> Result<Config> {
+        let mut config = Config::default();
+        let mut options = args;
+        if let Some(path) = args.first().filter(|arg| !arg.starts_with("--")) {
+            let path = fs::canonicalize(path).map_err(|error| {
+                anyhow!("Fatal error, can't open config file '{}': {}", path, error)
+            })?;
+            config.load_file(&path)?;
+            config.config_file = Some(path);
+            options = &args[1..];
+        }
+
+        let mut lines: Vec<Vec<String>> = vec![];
+        for arg in options {
+            match arg.strip_prefix("--") {
+                Some(name) => lines.push(vec![name.to_string()]),
+                None => match lines.last_mut() {
+                    Some(line) => line.push(arg.to_string()),
+                    None => return Err(anyhow!("Invalid argument '{}'", arg)),
+                },
+            }
+        }
+
+        for (index, line) in lines.iter().enumerate() {
+            config
+                .apply(&line[0], &line[1..])
+                .map_err(|message| config_error(index + 1, &line.join(" "), &message))?;
+        }
+
+        Ok(config)
+    }
+
+    pub fn load_file(&mut self, path: &Path) -> Result<()> {
+        self.load_file_with_depth(path, 0)
+    }
+
+    fn load_file_with_depth(&mut self, path: &Path, depth: usize) -> Result<()> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(anyhow!(
+                "Fatal error, too many nested includes at '{}'",
+                path.display()
+            ));
+        }
+
+        let content = fs::read_to_string(path).map_err(|error| {
+            anyhow!(
+                "Fatal error, can't open config file '{}': {}",
+                path.display(),
+                error
+            )
+        })?;
+        self.load_str_with_depth(&content, depth)
+    }
+
+    /// Applies every directive in `content`, one per line. `#` starts a comment
+    /// line and arguments may be quoted like inline commands.
+    pub fn load_str(&mut self, content: &str) -> Result<()> {
+        self.load_str_with_depth(content, 0)
+    }
+
+    fn load_str_with_depth(&mut self, content: &str, depth: usize) -> Result<()> {
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let args = split_inline_args(line.as_bytes())
+                .map_err(|_| {
+                    config_error(index + 1, line, "Unbalanced quotes in configuration line")
+                })?
+                .into_iter()
+                .map(|arg| String::from_utf8_lossy(&arg).into_owned())
+                .collect::<Vec<String>>();
+
+            if args[0].eq_ignore_ascii_case("include") && args.len() == 2 {
+                self.load_file_with_depth(Path::new(&args[1]), depth + 1)?;
+                continue;
+            }
+
+            self.apply(&args[0], &args[1..])
+                .map_err(|message| config_error(index + 1, line, &message))?;
+        }
+
+        Ok(())
+    }
+
+    /// Applies a single directive such as `port 6380`.
+    pub fn apply(&mut self, name: &str, values: &[String]) -> Result<(), String> {
+        match (&*name.to_lowercase(), values) {
+            ("bind", values) if !values.is_empty() => self.bind = values.to_vec(),
+            ("port", [value]) => self.port = parse_integer(value, 0, u16::MAX as i64)? as u16,
+            ("unixsocket", [value]) => {
+                self.unixsocket = Some(PathBuf::from(value)).filter(|_| !value.is_empty())
+            }
+            ("unixsocketperm", [value]) => self.unixsocketperm = parse_octal(value, 0, 0o777)?,
+            ("dir", [value]) => self.dir = PathBuf::from(value),
+            ("dbfilename", [value]) => {
+                if value.contains('/') {
+                    return Err("dbfilename can't be a path, just a filename".to_string());
+                }
+                self.dbfilename = value.to_string()
+            }
+            ("timeout", [value]) => self.timeout = parse_integer(value, 0, i32::MAX as i64)? as u64,
+            ("maxclients", [value]) => {
+                self.maxclients = parse_integer(value, 1, u32::MAX as i64)? as u64
+            }
+            ("proto-max-bulk-len", [value]) => {
+                self.proto_max_bulk_len = parse_memory(value, 1024 * 1024, i64::MAX)? as u64
+            }
+            ("proto-max-multibulk-len", [value]) => {
+                self.proto_max_multibulk_len = parse_integer(value, 1, i32::MAX as i64)? as u64
+            }
+            ("client-query-buffer-limit", [value]) => {
+                self.client_query_buffer_limit = parse_memory(value, 1024 * 1024, i64::MAX)? as u64
+            }
+            _ => return Err("Bad directive or wrong number of arguments".to_string()),
+        }
+
+        Ok(())
+    }
+
+    /// Returns the current value of a parameter the way `CONFIG GET` shows it.
+    pub fn get(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "bind" => self.bind.join(" "),
+            "port" => self.port.to_string(),
+            "unixsocket" => self
+                .unixsocket
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default(),
+            "unixsocketperm" => format!("{:o}", self.unixsocketperm),
+            "dir" => self.dir.display().to_string(),
+            "dbfilename" => self.dbfilename.clone(),
+            "timeout" => self.timeout.to_string(),
+            "maxclients" => self.maxclients.to_string(),
+            "proto-max-bulk-len" => self.proto_max_bulk_len.to_string(),
+            "proto-max-multibulk-len" => self.proto_max_multibulk_len.to_string(),
+            "client-query-buffer-limit" => self.client_query_buffer_limit.to_string(),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Sets a parameter from a `CONFIG SET` value. Multi-valued parameters such
+    /// as `bind` take their values separated by spaces.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let values = match name {
+            "bind" => value.split_whitespace().map(String::from).collect(),
+            _ => vec![value.to_string()],
+        };
+        self.apply(name, &values)
+    }
+
+    pub fn is_mutable(name: &str) -> bool {
+        !IMMUTABLE_PARAMETERS.contains(&name)
+    }
+
+    /// Writes the current values back to the config file. Existing directives
+    /// are updated in place, comments and unknown lines are kept, and changed
+    /// parameters missing from the file are appended at the end.
+    pub fn rewrite(&self) -> Result<()> {
+        let path = match &self.config_file {
+            Some(path) => path,
+            None => return Err(anyhow!("The server is running without a config file")),
+        };
+        let content = fs::read_to_string(path).unwrap_or_default();
+
+        let mut rewritten: HashSet<&str> = HashSet::new();
+        let mut lines: Vec<String> = vec![];
+        for line in content.lines() {
+            let name = split_inline_args(line.trim().as_bytes())
+                .ok()
+                .and_then(|args| args.into_iter().next())
+                .map(|name| String::from_utf8_lossy(&name).to_lowercase());
+            match name.and_then(|name| PARAMETERS.into_iter().find(|&known| known == name)) {
+                Some(name) => {
+                    // keep the first occurrence, later ones would override it
+                    if rewritten.insert(name) {
+                        lines.push(self.directive(name));
+                    }
+                }
+                None => lines.push(line.to_string()),
+            }
+        }
+
+        let defaults = Config::default();
+        let missing = PARAMETERS
+            .into_iter()
+            .filter(|name| !rewritten.contains(name) && self.get(name) != defaults.get(name))
+            .collect::<Vec<&str>>();
+        if !missing.is_empty() {
+            lines.push("# Generated by CONFIG REWRITE".to_string());
+            lines.extend(missing.into_iter().map(|name| self.directive(name)));
+        }
+
+        let temp_path = path.with_extension(format!("tmp-{}", std::process::id()));
+        fs::write(&temp_path, lines.join("\n") + "\n")?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    fn directive(&self, name: &str) -> String {
+        match name {
+            "bind" => format!("bind {}", self.bind.join(" ")),
+            _ => format!("{} {}", name, quote(&self.get(name).unwrap_or_default())),
+        }
+    }
+}
+
+fn parse_integer(value: &str, min: i64, max: i64) -> Result<i64, String> {
+    // as strict as command arguments, so `+5` or `05` are rejected
+    let integer = resp::parse_integer(value.as_bytes())
+        .ok_or_else(|| "argument couldn't be parsed into an integer".to_string())?;
+    if integer < min || integer > max {
+        return Err(format!(
+            "argument must be between {} and {} inclusive",
+            min, max
+        ));
+    }
+    Ok(integer)
+}
+
+/// Parses file permission bits written in octal, such as `700` or `0770`.
+fn parse_octal(value: &str, min: u32, max: u32) -> Result<u32, String> {
+    let integer = u32::from_str_radix(value, 8)
+        .map_err(|_| "argument couldn't be parsed into an integer".to_string())?;
+    if integer < min || integer > max {
+        return Err(format!(
+            "argument must be between {} and {} inclusive",
+            min, max
+        ));
+    }
+    Ok(integer)
+}
+
+/// Parses a memory amount such as `512mb`. Like Redis, `k`, `m` and `g` are
+/// powers of 1000 while `kb`, `mb` and `gb` are powers of 1024.
+fn parse_memory(value: &str, min: i64, max: i64) -> Result<i64, String> {
+    let lowercase = value.to_lowercase();
+    let units: [(&str, i64); 7] = [
+        ("kb", 1024),
+        ("mb", 1024 * 1024),
+        ("gb", 1024 * 1024 * 1024),
+        ("k", 1000),
+        ("m", 1000 * 1000),
+        ("g", 1000 * 1000 * 1000),
+        ("b", 1),
+    ];
+    let (digits, multiplier) = units
+        .into_iter()
+        .find_map(|(unit, multiplier)| {
+            lowercase
+                .strip_suffix(unit)
+                .map(|digits| (digits, multiplier))
+        })
+        .unwrap_or((&lowercase, 1));
+
+    let memory = digits
+        .parse::<i64>()
+        .ok()
+        .filter(|memory| *memory >= 0)
+        .and_then(|memory| memory.checked_mul(multiplier))
+        .ok_or_else(|| "argument must be a memory value".to_string())?;
+    if memory < min || memory > max {
+        return Err(format!(
+            "argument must be between {} and {} inclusive",
+            min, max
+        ));
+    }
+    Ok(memory)
+}
+
+/// Quotes a value so `split_inline_args` reads it back unchanged.
+fn quote(value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|char| char.is_whitespace() || char == '"' || char == '\'' || char == '\\');
+    if !needs_quotes {
+        return value.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    for char in value.chars() {
+        match char {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            _ => quoted.push(char),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl From<&Config> for DecoderLimits {
+    fn from(config: &Config) -> Self {
+        DecoderLimits {
+            max_bulk_len: config.proto_max_bulk_len as usize,
+            max_multibulk_len: config.proto_max_multibulk_len as usize,
+            max_buffer_len: config.client_query_buffer_limit as usize,
+            ..DecoderLimits::default()
+        }
+    }
+}
+
+fn config_error(line_number: usize, line: &str, message: &str) -> anyhow::Error {
+    anyhow!(
+        "\n*** FATAL CONFIG FILE ERROR (Redis {}) ***\nReading the configuration file, at line {}\n>>> '{}'\n{}",
+        REDIS_VERSION,
+        line_number,
+        line,
+        message
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn temp_file(name: &str, content: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("redis-config-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn it_should_use_defaults_without_arguments() {
+        assert_eq!(Config::from_args(&[]).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn it_should_read_command_line_flags() {
+        let config = Config::from_args(&args(&[
+            "--port",
+            "6380",
+            "--bind",
+            "0.0.0.0",
+            "::1",
+            "--dir",
+            "/tmp",
+            "--dbfilename",
+            "data.rdb",
+        ]))
+        .unwrap();
+
+        assert_eq!(config.port, 6380);
+        assert_eq!(config.bind, vec!["0.0.0.0", "::1"]);
+        assert_eq!(config.dir, PathBuf::from("/tmp"));
+        assert_eq!(config.dbfilename, "data.rdb");
+    }
+
+    #[test]
+    fn it_should_read_config_file_with_includes_and_let_flags_override_it() {
+        let included = temp_file("included.conf", "dbfilename included.rdb\n");
+        let main = temp_file(
+            "main.conf",
+            &format!(
+                "# comment\n\nport 7000\ninclude \"{}\"\ndir /var/lib/redis\n",
+                included.display()
+            ),
+        );
+
+        let config = Config::from_args(&args(&[main.to_str().unwrap(), "--port", "7001"])).unwrap();
+        assert_eq!(config.port, 7001);
+        assert_eq!(config.dbfilename, "included.rdb");
+        assert_eq!(config.dir, PathBuf::from("/var/lib/redis"));
+        assert_eq!(config.config_file, Some(fs::canonicalize(main).unwrap()));
+    }
+
+    #[test]
+    fn it_should_report_the_offending_line() {
+        let mut config = Config::default();
+        let error = config.load_str("port 6379\nport abc\n").unwrap_err();
+        let message = error.to_string();
+        assert!(message.contains("at line 2"));
+        assert!(message.contains(">>> 'port abc'"));
+        assert!(message.contains("argument couldn't be parsed into an integer"));
+
+        let error = config.load_str("unknown-directive yes\n").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Bad directive or wrong number of arguments"));
+    }
+
+    #[test]
+    fn it_should_get_and_set_parameters() {
+        let mut config = Config::default();
+        config.set("timeout", "30").unwrap();
+        config.set("dbfilename", "other.rdb").unwrap();
+        assert_eq!(config.get("timeout").unwrap(), "30");
+        assert_eq!(config.get("dbfilename").unwrap(), "other.rdb");
+        assert_eq!(config.get("maxclients").unwrap(), "10000");
+        assert_eq!(config.get("unknown"), None);
+
+        assert_eq!(
+            config.set("maxclients", "0").unwrap_err(),
+            "argument must be between 1 and 4294967295 inclusive"
+        );
+        for value in ["+5", "05", " 5"] {
+            assert_eq!(
+                config.set("maxclients", value).unwrap_err(),
+                "argument couldn't be parsed into an integer"
+            );
+        }
+        assert!(!Config::is_mutable("port"));
+        assert!(!Config::is_mutable("unixsocket"));
+        assert!(Config::is_mutable("timeout"));
+    }
+
+    #[test]
+    fn it_should_read_unix_socket_options() {
+        let config = Config::from_args(&args(&[
+            "--port",
+            "0",
+            "--unixsocket",
+            "/tmp/redis.sock",
+            "--unixsocketperm",
+            "770",
+        ]))
+        .unwrap();
+        assert_eq!(config.port, 0);
+        assert_eq!(config.unixsocket, Some(PathBuf::from("/tmp/redis.sock")));
+        assert_eq!(config.unixsocketperm, 0o770);
+        assert_eq!(config.get("unixsocketperm").unwrap(), "770");
+
+        let mut config = Config::default();
+        assert_eq!(config.get("unixsocket").unwrap(), "");
+        assert!(config
+            .load_str("unixsocketperm 800\n")
+            .unwrap_err()
+            .to_string()
+            .contains("argument couldn't be parsed into an integer"));
+        assert!(config
+            .load_str("unixsocketperm 1000\n")
+            .unwrap_err()
+            .to_string()
+            .contains("argument must be between 0 and 511 inclusive"));
+    }
+
+    #[test]
+    fn it_should_parse_memory_units() {
+        let mut config = Config::default();
+        for (value, expected) in [
+            ("2mb", 2 * 1024 * 1024),
+            ("2MB", 2 * 1024 * 1024),
+            ("2m", 2_000_000),
+            ("1gb", 1024 * 1024 * 1024),
+            ("3000000", 3_000_000),
+        ] {
+            config.set("proto-max-bulk-len", value).unwrap();
+            assert_eq!(config.proto_max_bulk_len, expected);
+        }
+
+        assert_eq!(
+            config.set("proto-max-bulk-len", "lots").unwrap_err(),
+            "argument must be a memory value"
+        );
+        assert!(config.set("client-query-buffer-limit", "1kb").is_err());
+    }
+
+    #[test]
+    fn it_should_rewrite_the_config_file() {
+        let path = temp_file(
+            "rewrite.conf",
+            "# keep this comment\ntimeout 10\n\ntimeout 20\n",
+        );
+        let mut config = Config::from_args(&args(&[path.to_str().unwrap()])).unwrap();
+        config.set("timeout", "60").unwrap();
+        config.set("dbfilename", "my data.rdb").unwrap();
+        config.rewrite().unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            content,
+            "# keep this comment\ntimeout 60\n\n\
+             # Generated by CONFIG REWRITE\ndbfilename \"my data.rdb\"\n"
+        );
+
+        let mut reloaded = Config::default();
+        reloaded.load_str("dbfilename \"my data.rdb\"").unwrap();
+        assert_eq!(reloaded.dbfilename, "my data.rdb");
+    }
+
+    #[test]
+    fn it_should_not_rewrite_without_a_config_file() {
+        let error = Config::default().rewrite().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The server is running without a config file"
+        );
+    }
+
+    #[test]
+    fn it_should_reject_recursive_includes() {
+        let path = std::env::temp_dir()
+            .join(format!("redis-config-test-{}", std::process::id()))
+            .join("recursive.conf");
+        temp_file("recursive.conf", &format!("include {}\n", path.display()));
+
+        assert!(Config::from_args(&args(&[path.to_str().unwrap()])).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use crate::resp::RESP;
+use crate::resp_decoder::{DecoderLimits, RESPDecoder};
+use anyhow::Result;
+use std::io::{self, ErrorKind};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Frames client requests over an async byte stream.
+///
+/// Bytes read from the socket are kept in the decoder, so a frame that spans
+/// several reads is returned once it is complete and pipelined frames that
+/// arrive in one read are returned one by one.
+pub struct Connection<S> {
+    stream: S,
+    decoder: RESPDecoder,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    pub fn new(stream: S) -> Self {
+        Connection {
+            stream,
+            decoder: RESPDecoder::new(vec![]),
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: DecoderLimits) {
+        self.decoder.set_limits(limits);
+    }
+
+    /// Waits for the next frame. Returns `Ok(None)` when the peer closes the
+    /// connection between frames.
+    pub async fn read_frame(&mut self) -> Result<Option<RESP>> {
+        loop {
+            if let Some(resp) = self.decoder.next_request()? {
+                return Ok(Some(resp));
+            }
+
+            if self.stream.read_buf(self.decoder.buffer_mut()).await? == 0 {
+                if self.decoder.has_remaining() {
+                    let error =
+                        io::Error::new(ErrorKind::ConnectionReset, "connection reset by peer");
+                    return Err(error.into());
+                }
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Returns a frame that is already buffered without waiting on the socket.
+    pub fn buffered_frame(&mut self) -> Result<Option<RESP>> {
+        Ok(self.decoder.next_request()?)
+    }
+
+    pub async fn write_all(&mut self, bytes: &[u8]) -> Result<()> {
+        self.stream.write_all(bytes).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Connection;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn it_should_read_frames_split_across_writes() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let mut connection = Connection::new(server);
+
+        client
+            .write_all(b"*2\r\n$4\r\necho\r\n$5\r\nhel")
+            .await
+            .unwrap();
+        client
+            .write_all(b"lo\r\n*1\r\n$4\r\nping\r\n")
+            .await
+            .unwrap();
+        drop(client);
+
+        let echo = connection.read_frame().await.unwrap().unwrap();
+        assert_eq!(echo.array()[1].string(), "hello");
+
+        let ping = connection.buffered_frame().unwrap().unwrap();
+        assert_eq!(ping.array()[0].string(), "ping");
+        assert!(connection.read_frame().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn it_should_fail_when_closed_in_the_middle_of_a_frame() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let mut connection = Connection::new(server);
+
+        client.write_all(b"*1\r\n$4\r\npi").await.unwrap();
+        drop(client);
+
+        assert!(connection.read_frame().await.is_err());
+
+        // cut between elements, once the decoder has consumed the bytes
+        let (mut client, server) = tokio::io::duplex(64);
+        let mut connection = Connection::new(server);
+
+        client.write_all(b"*2\r\n$4\r\necho\r\n").await.unwrap();
+        drop(client);
+
+        assert!(connection.read_frame().await.is_err());
+    }
+}
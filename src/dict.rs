@@ -0,0 +1,378 @@
+use bytes::Bytes;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+/// The number of buckets of an empty dict.
+const INITIAL_SIZE: usize = 4;
+
+/// The dict shrinks once fewer than one in this many buckets would be used.
+const MIN_FILL: usize = 8;
+
+/// Empty buckets a rehash step skips before giving up until the next step,
+/// as in Redis's `dictRehash`.
+const REHASH_EMPTY_VISITS: usize = 10;
+
+/// A hash table with a power-of-two number of buckets, like Redis's `dict`,
+/// so it can be walked with a reverse-binary cursor by `scan`.
+///
+/// It also rehashes like Redis: growing or shrinking allocates a second table
+/// and each insert, `get_mut` and remove moves one bucket over to it, so no
+/// single call pays for rehashing the whole table. Redis also rehashes from a
+/// timer while the dict is idle, which this does not.
+#[derive(Debug, Clone)]
+pub struct Dict<V> {
+    /// The table in use and, while rehashing, the table it moves into.
+    tables: [Table<V>; 2],
+    /// The next bucket of `tables[0]` to move, `None` when not rehashing.
+    rehash_index: Option<usize>,
+    hasher: RandomState,
+}
+
+#[derive(Debug, Clone)]
+struct Table<V> {
+    buckets: Vec<Vec<(Bytes, V)>>,
+    len: usize,
+}
+
+impl<V> Table<V> {
+    fn new(size: usize) -> Self {
+        Table {
+            buckets: (0..size).map(|_| vec![]).collect(),
+            len: 0,
+        }
+    }
+
+    fn mask(&self) -> u64 {
+        (self.buckets.len() - 1) as u64
+    }
+
+    fn bucket(&self, hash: u64) -> usize {
+        (hash & self.mask()) as usize
+    }
+
+    fn get(&self, hash: u64, key: &[u8]) -> Option<&V> {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        self.buckets[self.bucket(hash)]
+            .iter()
+            .find(|(entry_key, _)| entry_key == key)
+            .map(|(_, value)| value)
+    }
+
+    fn get_mut(&mut self, hash: u64, key: &[u8]) -> Option<&mut V> {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let bucket = self.bucket(hash);
+        self.buckets[bucket]
+            .iter_mut()
+            .find(|(entry_key, _)| entry_key == key)
+            .map(|(_, value)| value)
+    }
+
+    fn remove(&mut self, hash: u64, key: &[u8]) -> Option<V> {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let bucket = self.bucket(hash);
+        let position = self.buckets[bucket]
+            .iter()
+            .position(|(entry_key, _)| entry_key == key)?;
+        let (_, value) = self.buckets[bucket].swap_remove(position);
+        self.len -= 1;
+        Some(value)
+    }
+
+    fn push(&mut self, hash: u64, key: Bytes, value: V) {
+        let bucket = self.bucket(hash);
+        self.buckets[bucket].push((key, value));
+        self.len += 1;
+    }
+}
+
+impl<V> Default for Dict<V> {
+    fn default() -> Self {
+        Dict::new()
+    }
+}
+
+impl<V> Dict<V> {
+    pub fn new() -> Self {
+        Dict {
+            tables: [Table::new(INITIAL_SIZE), Table::new(0)],
+            rehash_index: None,
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables[0].len + self.tables[1].len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        let hash = self.hash(key);
+        self.tables.iter().find_map(|table| table.get(hash, key))
+    }
+
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        self.rehash_step();
+        let hash = self.hash(key);
+        self.tables
+            .iter_mut()
+            .find_map(|table| table.get_mut(hash, key))
+    }
+
+    /// Inserts `value`, returning the value `key` held before.
+    pub fn insert(&mut self, key: Bytes, value: V) -> Option<V> {
+        self.rehash_step();
+        let hash = self.hash(&key);
+        if let Some(entry_value) = self
+            .tables
+            .iter_mut()
+            .find_map(|table| table.get_mut(hash, &key))
+        {
+            return Some(std::mem::replace(entry_value, value));
+        }
+
+        match self.rehash_index {
+            Some(_) => self.tables[1].push(hash, key, value),
+            None => {
+                self.tables[0].push(hash, key, value);
+                if self.tables[0].len > self.tables[0].buckets.len() {
+                    self.start_rehash(self.tables[0].buckets.len() * 2);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        self.rehash_step();
+        let hash = self.hash(key);
+        let value = self
+            .tables
+            .iter_mut()
+            .find_map(|table| table.remove(hash, key))?;
+        let size = self.tables[0].buckets.len();
+        if self.rehash_index.is_none() && size > INITIAL_SIZE && self.len() * MIN_FILL < size {
+            self.start_rehash(self.len().next_power_of_two().max(INITIAL_SIZE));
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, &V)> {
+        self.tables
+            .iter()
+            .flat_map(|table| table.buckets.iter().flatten())
+            .map(|(key, value)| (key, value))
+    }
+
+    /// Calls `f` on the entries of the bucket `cursor` points at and returns
+    /// the cursor of the next bucket, 0 once every bucket has been visited.
+    ///
+    /// The cursor counts up from its most significant bit, as in Redis's
+    /// `dictScan`, so a key present for the whole walk is visited at least
+    /// once even if the dict grows or shrinks between calls. A key may be
+    /// visited more than once after a shrink.
+    pub fn scan(&self, cursor: u64, mut f: impl FnMut(&Bytes, &V)) -> u64 {
+        let mut visit = |table: &Table<V>, cursor: u64| {
+            for (key, value) in &table.buckets[(cursor & table.mask()) as usize] {
+                f(key, value);
+            }
+        };
+
+        if self.rehash_index.is_none() {
+            let mask = self.tables[0].mask();
+            visit(&self.tables[0], cursor);
+            return next_cursor(cursor, mask);
+        }
+
+        // while rehashing, visit the bucket of the smaller table and then
+        // every bucket of the larger one that it expands to
+        let (small, large) = if self.tables[0].buckets.len() <= self.tables[1].buckets.len() {
+            (&self.tables[0], &self.tables[1])
+        } else {
+            (&self.tables[1], &self.tables[0])
+        };
+        visit(small, cursor);
+        let mut cursor = cursor;
+        loop {
+            visit(large, cursor);
+            cursor = next_cursor(cursor, large.mask());
+            if cursor & (small.mask() ^ large.mask()) == 0 {
+                return cursor;
+            }
+        }
+    }
+
+    fn hash(&self, key: &[u8]) -> u64 {
+        self.hasher.hash_one(key)
+    }
+
+    /// Starts moving the entries into a table of `size` buckets.
+    fn start_rehash(&mut self, size: usize) {
+        self.tables[1] = Table::new(size);
+        self.rehash_index = Some(0);
+    }
+
+    /// Moves the next non-empty bucket into the new table, finishing the
+    /// rehash once the old table is empty.
+    fn rehash_step(&mut self) {
+        let mut index = match self.rehash_index {
+            Some(index) => index,
+            None => return,
+        };
+        if self.tables[0].len > 0 {
+            // the old table still has entries, so a non-empty bucket lies
+            // at or after `index`
+            let mut empty_visits = REHASH_EMPTY_VISITS;
+            while self.tables[0].buckets[index].is_empty() {
+                index += 1;
+                empty_visits -= 1;
+                if empty_visits == 0 {
+                    self.rehash_index = Some(index);
+                    return;
+                }
+            }
+            let bucket = std::mem::take(&mut self.tables[0].buckets[index]);
+            self.tables[0].len -= bucket.len();
+            for (key, value) in bucket {
+                let hash = self.hash(&key);
+                self.tables[1].push(hash, key, value);
+            }
+            index += 1;
+        }
+
+        if self.tables[0].len == 0 {
+            self.tables[0] = std::mem::replace(&mut self.tables[1], Table::new(0));
+            self.rehash_index = None;
+        } else {
+            self.rehash_index = Some(index);
+        }
+    }
+}
+
+/// Increments the bits of `cursor` under `mask`, counting from the most
+/// significant one.
+fn next_cursor(cursor: u64, mask: u64) -> u64 {
+    // set the bits above the mask so incrementing the reversed cursor
+    // carries into the bits that select the bucket
+    let cursor = (cursor | !mask).reverse_bits().wrapping_add(1);
+    cursor.reverse_bits()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dict;
+    use bytes::Bytes;
+    use std::collections::HashSet;
+
+    fn key(i: usize) -> Bytes {
+        Bytes::from(format!("key:{}", i))
+    }
+
+    #[test]
+    fn it_should_insert_get_and_remove() {
+        let mut dict = Dict::new();
+        for i in 0..100 {
+            assert_eq!(dict.insert(key(i), i), None);
+        }
+        assert_eq!(dict.insert(key(7), 70), Some(7));
+        assert_eq!(dict.len(), 100);
+        assert_eq!(dict.get(b"key:7"), Some(&70));
+
+        for i in 0..100 {
+            assert!(dict.remove(&key(i)).is_some());
+        }
+        assert!(dict.is_empty());
+        assert_eq!(dict.get(b"key:7"), None);
+    }
+
+    #[test]
+    fn it_should_rehash_one_bucket_per_change() {
+        let mut dict = Dict::new();
+        for i in 0..5 {
+            dict.insert(key(i), i);
+        }
+        assert_eq!(dict.rehash_index, Some(0));
+        assert_eq!(dict.tables[1].buckets.len(), 8);
+        for i in 0..5 {
+            assert_eq!(dict.get(&key(i)), Some(&i));
+        }
+
+        // each call moves one of the at most four non-empty old buckets
+        for _ in 0..4 {
+            dict.get_mut(b"missing");
+        }
+        assert_eq!(dict.rehash_index, None);
+        assert_eq!(dict.tables[0].buckets.len(), 8);
+        assert_eq!(dict.tables[0].len, 5);
+        assert!(dict.tables[1].buckets.is_empty());
+    }
+
+    #[test]
+    fn it_should_scan_both_tables_while_rehashing() {
+        let mut dict = Dict::new();
+        let mut i = 0;
+        while i < 100 || dict.rehash_index.is_none() {
+            dict.insert(key(i), ());
+            i += 1;
+        }
+        dict.remove(&key(0));
+        assert!(dict.tables[0].len > 0 && dict.tables[1].len > 0);
+
+        let mut seen = vec![];
+        let mut cursor = 0;
+        loop {
+            cursor = dict.scan(cursor, |key, _| seen.push(key.clone()));
+            if cursor == 0 {
+                break;
+            }
+        }
+        seen.sort();
+        let mut expected: Vec<_> = (1..i).map(key).collect();
+        expected.sort();
+        assert_eq!(seen, expected);
+    }
+
+    #[test]
+    fn it_should_scan_every_key_while_the_dict_is_resized() {
+        let mut dict = Dict::new();
+        for i in 0..200 {
+            dict.insert(key(i), ());
+        }
+
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        let mut steps = 0;
+        loop {
+            cursor = dict.scan(cursor, |key, _| {
+                seen.insert(key.clone());
+            });
+            steps += 1;
+            // grow to 1024 buckets, then shrink back below the start
+            if steps == 10 {
+                for i in 200..1000 {
+                    dict.insert(key(i), ());
+                }
+            }
+            if steps == 100 {
+                for i in 100..1000 {
+                    dict.remove(&key(i));
+                }
+            }
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        for i in 0..100 {
+            assert!(seen.contains(&key(i)), "{:?}", key(i));
+        }
+    }
+}
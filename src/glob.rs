@@ -0,0 +1,195 @@
+/// Matches `string` against a Redis glob `pattern`.
+///
+/// Supports `*`, `?`, character classes such as `[abc]`, `[a-z]` and `[^x]`,
+/// and `\` to escape the next character. Mirrors `stringmatchlen` in Redis.
+pub fn glob_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    glob_match_from(pattern, string, nocase, &mut false)
+}
+
+/// Sets `skip_longer_matches` once the pattern after a `*` has failed
+/// against every suffix of the string. A `*` further out cannot do better by
+/// consuming more, so it gives up too. Without this, patterns such as
+/// `*a*a*a*a*a*a*a*a*a*ab` backtrack exponentially (CVE-2022-36021).
+fn glob_match_from(
+    pattern: &[u8],
+    string: &[u8],
+    nocase: bool,
+    skip_longer_matches: &mut bool,
+) -> bool {
+    let mut pattern_position = 0;
+    let mut string_position = 0;
+
+    while pattern_position < pattern.len() {
+        match pattern[pattern_position] {
+            b'*' => {
+                // collapse consecutive stars, they match the same strings as one
+                while pattern.get(pattern_position + 1) == Some(&b'*') {
+                    pattern_position += 1;
+                }
+                if pattern_position + 1 == pattern.len() {
+                    return true;
+                }
+                for start in string_position..=string.len() {
+                    let rest = &pattern[pattern_position + 1..];
+                    if glob_match_from(rest, &string[start..], nocase, skip_longer_matches) {
+                        return true;
+                    }
+                    if *skip_longer_matches {
+                        return false;
+                    }
+                }
+                *skip_longer_matches = true;
+                return false;
+            }
+            b'?' => {
+                if string_position == string.len() {
+                    return false;
+                }
+                string_position += 1;
+            }
+            b'[' => {
+                let byte = match string.get(string_position) {
+                    Some(&byte) => byte,
+                    None => return false,
+                };
+                let (matched, next_position) =
+                    match_class(pattern, pattern_position + 1, byte, nocase);
+                if !matched {
+                    return false;
+                }
+                pattern_position = next_position;
+                string_position += 1;
+            }
+            expected => {
+                let expected = if expected == b'\\' && pattern_position + 1 < pattern.len() {
+                    pattern_position += 1;
+                    pattern[pattern_position]
+                } else {
+                    expected
+                };
+                match string.get(string_position) {
+                    Some(&byte) if equal(expected, byte, nocase) => string_position += 1,
+                    _ => return false,
+                }
+            }
+        }
+        pattern_position += 1;
+    }
+
+    string_position == string.len()
+}
+
+/// Matches `byte` against the class that starts right after `[`. Returns
+/// whether it matched and the position of the closing `]`.
+fn match_class(pattern: &[u8], start: usize, byte: u8, nocase: bool) -> (bool, usize) {
+    let mut position = start;
+    let negate = pattern.get(position) == Some(&b'^');
+    if negate {
+        position += 1;
+    }
+
+    let mut matched = false;
+    while position < pattern.len() && pattern[position] != b']' {
+        if pattern[position] == b'\\' && position + 1 < pattern.len() {
+            position += 1;
+            matched |= equal(pattern[position], byte, nocase);
+        } else if pattern.get(position + 1) == Some(&b'-') && position + 2 < pattern.len() {
+            let (mut low, mut high) = (pattern[position], pattern[position + 2]);
+            if low > high {
+                std::mem::swap(&mut low, &mut high);
+            }
+            let (byte, low, high) = if nocase {
+                (
+                    byte.to_ascii_lowercase(),
+                    low.to_ascii_lowercase(),
+                    high.to_ascii_lowercase(),
+                )
+            } else {
+                (byte, low, high)
+            };
+            matched |= low <= byte && byte <= high;
+            position += 2;
+        } else {
+            matched |= equal(pattern[position], byte, nocase);
+        }
+        position += 1;
+    }
+
+    // an unterminated class ends at the last character of the pattern
+    let end = position.min(pattern.len() - 1);
+    (matched != negate, end)
+}
+
+fn equal(left: u8, right: u8, nocase: bool) -> bool {
+    if nocase {
+        left.eq_ignore_ascii_case(&right)
+    } else {
+        left == right
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn it_should_match_glob_patterns() {
+        let tests: [(&str, &str, bool); 20] = [
+            ("*", "", true),
+            ("*", "anything", true),
+            ("h?llo", "hello", true),
+            ("h?llo", "hllo", false),
+            ("h*llo", "heeeello", true),
+            ("h*llo", "hello world", false),
+            ("h[ae]llo", "hallo", true),
+            ("h[ae]llo", "hillo", false),
+            ("h[^e]llo", "hallo", true),
+            ("h[^e]llo", "hello", false),
+            ("h[a-b]llo", "hbllo", true),
+            ("h[b-a]llo", "hallo", true),
+            ("h[a-b]llo", "hcllo", false),
+            ("h\\*llo", "h*llo", true),
+            ("h\\*llo", "hello", false),
+            ("[\\]]", "]", true),
+            ("user:*:name", "user:1000:name", true),
+            ("*:name", "user:1000:age", false),
+            ("a**b", "ab", true),
+            ("abc", "ab", false),
+        ];
+
+        for (pattern, string, expected) in tests {
+            assert_eq!(
+                glob_match(pattern.as_bytes(), string.as_bytes(), false),
+                expected,
+                "{} against {}",
+                pattern,
+                string
+            );
+        }
+    }
+
+    #[test]
+    fn it_should_match_case_insensitively() {
+        assert!(glob_match(b"MAX*", b"maxclients", true));
+        assert!(glob_match(b"[A-C]x", b"bx", true));
+        assert!(!glob_match(b"MAX*", b"maxclients", false));
+    }
+
+    #[test]
+    fn it_should_not_backtrack_exponentially() {
+        let started = Instant::now();
+        let string = "a".repeat(40);
+        assert!(!glob_match(
+            b"*a*a*a*a*a*a*a*a*a*ab",
+            string.as_bytes(),
+            false
+        ));
+        assert!(glob_match(
+            b"*a*a*a*a*a*a*a*a*a*a",
+            string.as_bytes(),
+            false
+        ));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -1,4 +1,13 @@
+pub mod client;
+pub mod clock;
 pub mod command_executor;
+pub mod command_table;
+pub mod config;
+pub mod connection;
+pub mod dict;
+pub mod glob;
 pub mod resp;
 pub mod resp_decoder;
+pub mod resp_encoder;
+pub mod server;
 pub mod store;
@@ -1,62 +1,304 @@
-use anyhow::anyhow;
 use anyhow::Result;
-use redis_starter_rust::command_executor::CommandExecutor;
-use redis_starter_rust::resp_decoder::RESPDecoder;
-use redis_starter_rust::store::Store;
-use std::io::prelude::*;
+use redis_starter_rust::client::Client;
+use redis_starter_rust::command_executor::{sanitize_error_message, CommandExecutor};
+use redis_starter_rust::config::Config;
+use redis_starter_rust::connection::Connection;
+use redis_starter_rust::resp::RESP;
+use redis_starter_rust::resp_decoder::{DecodeError, DecoderLimits};
+use redis_starter_rust::resp_encoder::RESPEncoder;
+use redis_starter_rust::server::{Server, Stats};
+use std::fs;
 use std::io::ErrorKind;
-use std::net::{TcpListener, TcpStream};
-use std::sync::{Arc, RwLock};
-use std::thread;
-
-fn main() -> Result<()> {
-    let listener = TcpListener::bind("127.0.0.1:6379").unwrap();
-    let store = Arc::new(RwLock::new(Store::new()));
-    for stream in listener.incoming() {
-        // TODO: using thread pool
-        let stream = stream?;
-        let store = Arc::clone(&store);
-        thread::spawn(move || {
-            handle_request(stream, store).unwrap_or_else(|error| eprintln!("{:?}", error));
-        });
+use std::os::unix::fs::PermissionsExt;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, PoisonError};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+/// How often an idle connection re-reads `timeout`, so CONFIG SET applies to
+/// clients that are already connected.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often expired keys are looked for, Redis's default `hz` of 10.
+const ACTIVE_EXPIRE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long one active expire cycle may hold the store, a quarter of the
+/// interval like in Redis.
+const ACTIVE_EXPIRE_TIME_LIMIT: Duration = Duration::from_millis(25);
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let mut config = match Config::from_args(&args) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+    std::env::set_current_dir(&config.dir)?;
+    config.dir = std::env::current_dir()?;
+
+    if config.port == 0 && config.unixsocket.is_none() {
+        eprintln!("Configured to not listen anywhere, exiting.");
+        std::process::exit(1);
+    }
+
+    // port 0 disables TCP, e.g. to serve only on the Unix socket
+    let mut listeners = vec![];
+    if config.port != 0 {
+        for address in &config.bind {
+            listeners.push(TcpListener::bind((address.as_str(), config.port)).await?);
+        }
+    }
+    let unix_listener = match &config.unixsocket {
+        Some(path) => Some(bind_unix_socket(path, config.unixsocketperm)?),
+        None => None,
+    };
+
+    let unixsocket = config.unixsocket.clone();
+    let server = Arc::new(Server::new(config));
+
+    // every connection holds a clone of `drain`, so `drained` yields `None`
+    // once the last of them has finished
+    let (drain, mut drained) = mpsc::channel::<()>(1);
+    for listener in listeners {
+        tokio::spawn(accept_loop(listener, Arc::clone(&server), drain.clone()));
     }
+    if let Some(listener) = unix_listener {
+        tokio::spawn(accept_unix_loop(
+            listener,
+            Arc::clone(&server),
+            drain.clone(),
+        ));
+    }
+    drop(drain);
+    tokio::spawn(active_expire_loop(Arc::clone(&server)));
+
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
+    tokio::select! {
+        _ = sigterm.recv() => eprintln!("Received SIGTERM scheduling shutdown..."),
+        _ = sigint.recv() => eprintln!("Received SIGINT scheduling shutdown..."),
+        _ = server.shutdown_requested() => eprintln!("User requested shutdown..."),
+    }
+    server.shutdown();
 
+    // commands that are already running finish and get their replies; a
+    // second signal skips the wait
+    tokio::select! {
+        _ = drained.recv() => {}
+        _ = sigterm.recv() => force_exit(),
+        _ = sigint.recv() => force_exit(),
+    }
+
+    // there is no persistence to flush yet, only the socket file to clean up
+    if let Some(path) = unixsocket {
+        fs::remove_file(path).unwrap_or_else(|error| eprintln!("{:?}", error));
+    }
+    eprintln!("Redis is now ready to exit, bye bye...");
     Ok(())
 }
 
-fn handle_request(mut stream: TcpStream, store: Arc<RwLock<Store>>) -> Result<()> {
+fn force_exit() -> ! {
+    eprintln!("You insist... exiting now.");
+    std::process::exit(1);
+}
+
+/// Binds the Unix socket, replacing a socket file left behind by a previous
+/// run, and applies `unixsocketperm` when it is set.
+fn bind_unix_socket(path: &std::path::Path, perm: u32) -> Result<UnixListener> {
+    match fs::remove_file(path) {
+        Err(error) if error.kind() != ErrorKind::NotFound => return Err(error.into()),
+        _ => {}
+    }
+    let listener = UnixListener::bind(path)?;
+    if perm != 0 {
+        fs::set_permissions(path, fs::Permissions::from_mode(perm))?;
+    }
+    Ok(listener)
+}
+
+/// Deletes expired keys that are never looked up again, so they do not stay
+/// in memory forever.
+async fn active_expire_loop(server: Arc<Server>) {
+    let mut interval = tokio::time::interval(ACTIVE_EXPIRE_INTERVAL);
     loop {
-        let mut buffer = [0; 1024];
-        let byte_count = stream
-            .read(&mut buffer)
-            .expect("error while reading from connection");
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = server.shutdown_requested() => return,
+        }
+        match server.store.write() {
+            Ok(mut store) => {
+                store.active_expire_cycle(ACTIVE_EXPIRE_TIME_LIMIT);
+            }
+            Err(error) => eprintln!("{:?}", error),
+        }
+    }
+}
 
-        if byte_count == 0 {
-            break;
+async fn accept_loop(listener: TcpListener, server: Arc<Server>, drain: mpsc::Sender<()>) {
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = server.shutdown_requested() => return,
+        };
+        match accepted {
+            Ok((stream, _)) => {
+                tokio::spawn(serve_client(stream, Arc::clone(&server), drain.clone()));
+            }
+            // e.g. running out of file descriptors; keep serving existing clients
+            Err(error) => eprintln!("{:?}", error),
         }
+    }
+}
 
-        let mut decoder = RESPDecoder::new(buffer[..byte_count].to_vec());
-        let resp = decoder.next_resp()?;
-        let args = resp.array();
+async fn accept_unix_loop(listener: UnixListener, server: Arc<Server>, drain: mpsc::Sender<()>) {
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = server.shutdown_requested() => return,
+        };
+        match accepted {
+            Ok((stream, _)) => {
+                tokio::spawn(serve_client(stream, Arc::clone(&server), drain.clone()));
+            }
+            Err(error) => eprintln!("{:?}", error),
+        }
+    }
+}
 
-        if args.len() == 0 {
-            continue;
+async fn serve_client<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: S,
+    server: Arc<Server>,
+    _drain: mpsc::Sender<()>,
+) {
+    Stats::increment(&server.stats.total_connections_received);
+    let connected_clients = server
+        .stats
+        .connected_clients
+        .fetch_add(1, Ordering::Relaxed);
+    // the config is plain values, so it stays usable even if a handler
+    // panicked while holding the lock
+    let maxclients = server
+        .config
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .maxclients;
+    let result = if connected_clients >= maxclients {
+        Stats::increment(&server.stats.rejected_connections);
+        reject_client(stream).await
+    } else {
+        handle_request(stream, &server).await
+    };
+    server
+        .stats
+        .connected_clients
+        .fetch_sub(1, Ordering::Relaxed);
+    result.unwrap_or_else(|error| eprintln!("{:?}", error));
+}
+
+async fn reject_client<S: AsyncRead + AsyncWrite + Unpin>(stream: S) -> Result<()> {
+    let mut connection = Connection::new(stream);
+    connection
+        .write_all(b"-ERR max number of clients reached\r\n")
+        .await
+}
+
+async fn handle_request<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: S,
+    server: &Server,
+) -> Result<()> {
+    let mut connection = Connection::new(stream);
+    let mut client = Client::new();
+    loop {
+        let mut response: Vec<u8> = vec![];
+        let mut next_resp = read_frame_before_timeout(&mut connection, server).await;
+        if let Ok(None) = next_resp {
+            break;
         }
 
-        let command_executer = CommandExecutor::new(args, &store);
-        let response = command_executer.execute();
-        match stream.write(&response[..]) {
-            Ok(_) => {
-                stream.flush().expect("error while writing to connection");
+        while let Ok(Some(resp)) = next_resp {
+            let args = resp.array();
+            if !args.is_empty() {
+                let mut command_executer = CommandExecutor::new(args, server, &mut client);
+                response.append(&mut command_executer.execute());
             }
-            Err(error) => match error.kind() {
-                ErrorKind::BrokenPipe => {
-                    break;
+            // commands pipelined after SHUTDOWN are dropped
+            next_resp = if client.closing {
+                Ok(None)
+            } else {
+                connection.buffered_frame()
+            };
+        }
+
+        // replies to the commands before a malformed frame are still sent,
+        // then the client is told why and disconnected
+        let decode_error = next_resp.err();
+        let protocol_error = decode_error
+            .as_ref()
+            .and_then(|error| error.downcast_ref::<DecodeError>());
+        if let Some(error @ DecodeError::Protocol(_)) = protocol_error {
+            let reply = RESP::new_error("ERR", &sanitize_error_message(&error.to_string()));
+            RESPEncoder::new(client.protocol).encode_into(&reply, &mut response);
+        }
+
+        if !response.is_empty() {
+            if let Err(error) = connection.write_all(&response[..]).await {
+                match error
+                    .downcast_ref::<std::io::Error>()
+                    .map(|error| error.kind())
+                {
+                    Some(ErrorKind::BrokenPipe) | Some(ErrorKind::ConnectionReset) => break,
+                    _ => return Err(error),
                 }
-                other_error => return Err(anyhow!("{}", other_error)),
-            },
+            }
+        }
+
+        if let Some(error) = decode_error {
+            return Err(error);
+        }
+        if client.closing {
+            break;
         }
     }
 
     Ok(())
 }
+
+/// Reads the next frame, or returns `Ok(None)` once the client has been idle
+/// for longer than the configured `timeout` or the server is shutting down.
+async fn read_frame_before_timeout<S: AsyncRead + AsyncWrite + Unpin>(
+    connection: &mut Connection<S>,
+    server: &Server,
+) -> Result<Option<RESP>> {
+    let idle_since = Instant::now();
+    loop {
+        let (timeout, limits) = {
+            let config = server.config.read().unwrap_or_else(PoisonError::into_inner);
+            (config.timeout, DecoderLimits::from(&*config))
+        };
+        connection.set_limits(limits);
+        let wait = if timeout == 0 {
+            IDLE_CHECK_INTERVAL
+        } else {
+            let deadline = idle_since + Duration::from_secs(timeout);
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            IDLE_CHECK_INTERVAL.min(deadline - Instant::now())
+        };
+
+        // reading is cancel safe: bytes already read stay in the decoder
+        tokio::select! {
+            biased;
+            _ = server.shutdown_requested() => return Ok(None),
+            frame = connection.read_frame() => return frame,
+            _ = tokio::time::sleep(wait) => {}
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use bytes::Bytes;
 use std::convert::TryFrom;
 
 pub const SIMPLE_STRING: char = '+';
@@ -5,9 +6,21 @@ pub const ERROR: char = '-';
 pub const INTEGER: char = ':';
 pub const BULK_STRING: char = '$';
 pub const ARRAY: char = '*';
+pub const NULL: char = '_';
+pub const BOOLEAN: char = '#';
+pub const DOUBLE: char = ',';
+pub const BIG_NUMBER: char = '(';
+pub const BULK_ERROR: char = '!';
+pub const VERBATIM_STRING: char = '=';
+pub const MAP: char = '%';
+pub const SET: char = '~';
+pub const ATTRIBUTE: char = '|';
+pub const PUSH: char = '>';
 
-pub const CRLF: &'static str = "\r\n";
-pub const NULL_STRING: &'static str = "$-1\r\n";
+pub const CRLF: &str = "\r\n";
+pub const NULL_STRING: &str = "$-1\r\n";
+pub const NULL_ARRAY: &str = "*-1\r\n";
+pub const RESP3_NULL: &str = "_\r\n";
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Type {
@@ -16,6 +29,39 @@ pub enum Type {
     Integer,
     BulkString,
     Array,
+    Null,
+    Boolean,
+    Double,
+    BigNumber,
+    BulkError,
+    VerbatimString,
+    Map,
+    Set,
+    Attribute,
+    Push,
+}
+
+impl Type {
+    /// The type byte that prefixes a value of this type on the wire.
+    pub fn as_char(&self) -> char {
+        match self {
+            Type::SimpleString => SIMPLE_STRING,
+            Type::Error => ERROR,
+            Type::Integer => INTEGER,
+            Type::BulkString => BULK_STRING,
+            Type::Array => ARRAY,
+            Type::Null => NULL,
+            Type::Boolean => BOOLEAN,
+            Type::Double => DOUBLE,
+            Type::BigNumber => BIG_NUMBER,
+            Type::BulkError => BULK_ERROR,
+            Type::VerbatimString => VERBATIM_STRING,
+            Type::Map => MAP,
+            Type::Set => SET,
+            Type::Attribute => ATTRIBUTE,
+            Type::Push => PUSH,
+        }
+    }
 }
 
 impl TryFrom<char> for Type {
@@ -28,6 +74,16 @@ impl TryFrom<char> for Type {
             INTEGER => Ok(Type::Integer),
             BULK_STRING => Ok(Type::BulkString),
             ARRAY => Ok(Type::Array),
+            NULL => Ok(Type::Null),
+            BOOLEAN => Ok(Type::Boolean),
+            DOUBLE => Ok(Type::Double),
+            BIG_NUMBER => Ok(Type::BigNumber),
+            BULK_ERROR => Ok(Type::BulkError),
+            VERBATIM_STRING => Ok(Type::VerbatimString),
+            MAP => Ok(Type::Map),
+            SET => Ok(Type::Set),
+            ATTRIBUTE => Ok(Type::Attribute),
+            PUSH => Ok(Type::Push),
             _ => Err(format!("invalid data type byte: {}", data_type_char)),
         }
     }
@@ -35,19 +91,49 @@ impl TryFrom<char> for Type {
 
 impl std::fmt::Display for Type {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let str = match self {
-            Type::SimpleString => SIMPLE_STRING,
-            Type::Error => ERROR,
-            Type::Integer => INTEGER,
-            Type::BulkString => BULK_STRING,
-            Type::Array => ARRAY,
-        };
-
-        write!(f, "{}", str)?;
+        write!(f, "{}", self.as_char())?;
         Ok(())
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ProtocolVersion {
+    RESP2,
+    RESP3,
+}
+
+impl ProtocolVersion {
+    pub fn number(&self) -> i64 {
+        match self {
+            ProtocolVersion::RESP2 => 2,
+            ProtocolVersion::RESP3 => 3,
+        }
+    }
+}
+
+impl TryFrom<i64> for ProtocolVersion {
+    type Error = String;
+
+    fn try_from(version: i64) -> Result<Self, String> {
+        match version {
+            2 => Ok(ProtocolVersion::RESP2),
+            3 => Ok(ProtocolVersion::RESP3),
+            _ => Err(format!("unsupported protocol version: {}", version)),
+        }
+    }
+}
+
+/// Parses an integer as strictly as Redis' `string2ll`: an optional `-`
+/// followed by digits, with no `+`, spaces or leading zeros.
+pub fn parse_integer(data: &[u8]) -> Option<i64> {
+    let digits = data.strip_prefix(b"-").unwrap_or(data);
+    let leading_zero = digits.first() == Some(&b'0') && data.len() > 1;
+    if digits.is_empty() || leading_zero || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(data).ok()?.parse::<i64>().ok()
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct RESPError {
     pub error_type: String,
@@ -66,31 +152,159 @@ impl RESPError {
 #[derive(Debug, Clone, PartialEq)]
 pub struct RESP {
     pub value_type: Type,
-    data: Vec<u8>,
+    data: Bytes,
     children: Vec<RESP>,
+    attribute: Option<Box<RESP>>,
 }
 
 impl RESP {
-    pub fn new(value_type: Type, data: Vec<u8>, children: Vec<RESP>) -> Self {
+    pub fn new(value_type: Type, data: impl Into<Bytes>, children: Vec<RESP>) -> Self {
         RESP {
             value_type,
-            data,
+            data: data.into(),
             children,
+            attribute: None,
         }
     }
 
+    pub fn new_simple_string(value: &str) -> Self {
+        RESP::new(Type::SimpleString, value.to_string(), vec![])
+    }
+
+    pub fn new_error(error_type: &str, message: &str) -> Self {
+        RESP::new(Type::Error, format!("{} {}", error_type, message), vec![])
+    }
+
+    pub fn new_integer(value: i64) -> Self {
+        RESP::new(Type::Integer, value.to_string(), vec![])
+    }
+
+    pub fn new_bulk_string(value: impl Into<Bytes>) -> Self {
+        RESP::new(Type::BulkString, value, vec![])
+    }
+
+    pub fn new_array(children: Vec<RESP>) -> Self {
+        RESP::new(Type::Array, children.len().to_string(), children)
+    }
+
+    pub fn new_null() -> Self {
+        RESP::new(Type::Null, Bytes::new(), vec![])
+    }
+
+    /// The RESP2 null array (`*-1`), which RESP3 sends as a plain null.
+    pub fn new_null_array() -> Self {
+        RESP::new(Type::Array, "-1", vec![])
+    }
+
+    pub fn new_boolean(value: bool) -> Self {
+        RESP::new(Type::Boolean, if value { "t" } else { "f" }, vec![])
+    }
+
+    pub fn new_double(value: f64) -> Self {
+        let data = if value.is_nan() {
+            "nan".to_string()
+        } else if value.is_infinite() {
+            if value > 0.0 { "inf" } else { "-inf" }.to_string()
+        } else {
+            value.to_string()
+        };
+        RESP::new(Type::Double, data, vec![])
+    }
+
+    pub fn new_big_number(digits: &str) -> Self {
+        RESP::new(Type::BigNumber, digits.to_string(), vec![])
+    }
+
+    pub fn new_bulk_error(error_type: &str, message: &str) -> Self {
+        RESP::new(
+            Type::BulkError,
+            format!("{} {}", error_type, message),
+            vec![],
+        )
+    }
+
+    /// `format` is the three-character content type, such as `txt` or `mkd`.
+    pub fn new_verbatim_string(format: &str, text: &[u8]) -> Self {
+        let mut data = format!("{}:", format).into_bytes();
+        data.extend_from_slice(text);
+        RESP::new(Type::VerbatimString, data, vec![])
+    }
+
+    pub fn new_map(pairs: Vec<(RESP, RESP)>) -> Self {
+        let pair_count = pairs.len();
+        let children = pairs
+            .into_iter()
+            .flat_map(|(key, value)| [key, value])
+            .collect();
+        RESP::new(Type::Map, pair_count.to_string(), children)
+    }
+
+    pub fn new_set(children: Vec<RESP>) -> Self {
+        RESP::new(Type::Set, children.len().to_string(), children)
+    }
+
+    pub fn new_push(children: Vec<RESP>) -> Self {
+        RESP::new(Type::Push, children.len().to_string(), children)
+    }
+
+    pub fn new_attribute(pairs: Vec<(RESP, RESP)>) -> Self {
+        let mut attribute = RESP::new_map(pairs);
+        attribute.value_type = Type::Attribute;
+        attribute
+    }
+
+    /// Attaches a RESP3 attribute map that precedes this value on the wire.
+    pub fn with_attribute(mut self, attribute: RESP) -> Self {
+        self.attribute = Some(Box::new(attribute));
+        self
+    }
+
+    /// Returns the raw payload without any UTF-8 conversion.
+    pub fn bytes(&self) -> &Bytes {
+        &self.data
+    }
+
     pub fn string(&self) -> String {
         String::from_utf8_lossy(&self.data).into_owned()
     }
 
-    pub fn integer(&self) -> i64 {
-        String::from_utf8_lossy(&self.data)
-            .to_owned()
-            .parse::<i64>()
-            .unwrap()
+    pub fn integer(&self) -> Option<i64> {
+        parse_integer(&self.data)
     }
 
     pub fn array(&self) -> &Vec<RESP> {
         &self.children
     }
+
+    pub fn boolean(&self) -> bool {
+        &self.data[..] == b"t"
+    }
+
+    pub fn double(&self) -> Option<f64> {
+        match &self.data[..] {
+            b"inf" => Some(f64::INFINITY),
+            b"-inf" => Some(f64::NEG_INFINITY),
+            _ => std::str::from_utf8(&self.data).ok()?.parse::<f64>().ok(),
+        }
+    }
+
+    /// Returns the key-value pairs of a map or attribute.
+    pub fn pairs(&self) -> Vec<(&RESP, &RESP)> {
+        self.children
+            .chunks(2)
+            .map(|pair| (&pair[0], &pair[1]))
+            .collect()
+    }
+
+    pub fn attribute(&self) -> Option<&RESP> {
+        self.attribute.as_deref()
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.value_type == Type::Null || self.is_null_array()
+    }
+
+    pub fn is_null_array(&self) -> bool {
+        self.value_type == Type::Array && &self.data[..] == b"-1"
+    }
 }
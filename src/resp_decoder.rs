@@ -1,107 +1,540 @@
 use crate::resp::{Type, RESP};
-use anyhow::{anyhow, Result};
-use std::io::{self, BufRead, Read};
+use bytes::{Buf, Bytes, BytesMut};
+use std::io::Cursor;
+
+type Result<T> = std::result::Result<T, DecodeError>;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DecodeError {
+    /// The remaining bytes of the frame have not been received yet.
+    /// `next_resp` and `next_request` report this as `Ok(None)`.
+    #[error("incomplete frame")]
+    Incomplete,
+    /// A client kept sending bytes that never complete a frame. Redis closes
+    /// such clients without a reply.
+    #[error("closing client that reached max query buffer length")]
+    QueryBufferLimit,
+    /// Malformed input. The client is sent this error, then disconnected.
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+}
+
+/// Bounds on what a peer may announce or buffer, so a malformed or hostile
+/// frame cannot make the server allocate unbounded memory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DecoderLimits {
+    pub max_bulk_len: usize,
+    pub max_multibulk_len: usize,
+    pub max_inline_len: usize,
+    pub max_buffer_len: usize,
+    /// How deeply aggregates may nest in a reply decoded by `next_resp`.
+    pub max_nesting_depth: usize,
+}
+
+impl Default for DecoderLimits {
+    fn default() -> Self {
+        DecoderLimits {
+            max_bulk_len: 512 * 1024 * 1024,
+            max_multibulk_len: 1024 * 1024,
+            max_inline_len: 64 * 1024,
+            max_buffer_len: 1024 * 1024 * 1024,
+            max_nesting_depth: 64,
+        }
+    }
+}
+
+/// An aggregate whose elements have not all been received yet.
+struct PartialAggregate {
+    value_type: Type,
+    bytes: Bytes,
+    remaining: usize,
+    children: Vec<RESP>,
+    /// An attribute read at this level, waiting for the value it describes.
+    attribute: Option<RESP>,
+}
+
+/// What the bytes at the front of the buffer decode to: a whole value, or
+/// the header of an aggregate whose elements follow.
+enum Token {
+    Value(RESP),
+    Aggregate(Type, Bytes, usize),
+}
 
 pub struct RESPDecoder {
-    cursor: io::Cursor<Vec<u8>>,
+    buffer: BytesMut,
+    limits: DecoderLimits,
+    /// The aggregates the next element belongs to, outermost first.
+    stack: Vec<PartialAggregate>,
+    /// An attribute read before a top-level value.
+    attribute: Option<RESP>,
+    /// Bytes of the current frame already consumed from `buffer`.
+    frame_len: usize,
 }
 
 impl RESPDecoder {
     pub fn new(buffer: Vec<u8>) -> Self {
         RESPDecoder {
-            cursor: io::Cursor::new(buffer),
+            buffer: BytesMut::from(&buffer[..]),
+            limits: DecoderLimits::default(),
+            stack: vec![],
+            attribute: None,
+            frame_len: 0,
         }
     }
 
-    pub fn next_resp(&mut self) -> Result<RESP> {
-        let data_type = self.read_data_type()?;
-        let result = match data_type {
-            Type::SimpleString => self.decode_simple_string()?,
-            Type::BulkString => self.decode_bulk_string()?,
-            Type::Integer => self.decode_integer()?,
-            Type::Array => self.decode_array()?,
-            Type::Error => self.decode_error()?,
-        };
-        Ok(result)
+    pub fn set_limits(&mut self, limits: DecoderLimits) {
+        self.limits = limits;
+    }
+
+    /// Appends bytes received from the connection to the pending buffer.
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Exposes the pending buffer so a socket can read straight into it.
+    pub fn buffer_mut(&mut self) -> &mut BytesMut {
+        &mut self.buffer
+    }
+
+    /// Whether part of a frame is pending: bytes not decoded yet, or the
+    /// elements of an aggregate decoded so far.
+    pub fn has_remaining(&self) -> bool {
+        !self.buffer.is_empty() || !self.stack.is_empty() || self.attribute.is_some()
+    }
+
+    /// Decodes the next complete frame from the buffer.
+    ///
+    /// Returns `Ok(None)` when the buffer holds only part of a frame. The
+    /// elements decoded so far are kept, so once `extend` supplies the rest
+    /// decoding resumes at the element it stopped in instead of starting the
+    /// frame over.
+    pub fn next_resp(&mut self) -> Result<Option<RESP>> {
+        self.next_frame(false)
+    }
+
+    /// Decodes the next command sent by a client. Like Redis, only an inline
+    /// command or a multibulk of bulk strings is accepted, so a client cannot
+    /// make the server nest aggregates.
+    pub fn next_request(&mut self) -> Result<Option<RESP>> {
+        self.next_frame(true)
     }
 
-    fn decode_simple_string(&mut self) -> Result<RESP> {
-        let bytes = self.read_until_crlf()?;
-        Ok(RESP::new(Type::SimpleString, bytes, vec![]))
+    fn next_frame(&mut self, request: bool) -> Result<Option<RESP>> {
+        loop {
+            let mut cursor = Cursor::new(&self.buffer[..]);
+            let top_level = self.stack.is_empty();
+            let token = if request {
+                read_request_token(&mut cursor, &self.limits, top_level)
+            } else {
+                read_token(&mut cursor, &self.limits, top_level)
+            };
+            let token = match token {
+                Ok(token) => token,
+                Err(DecodeError::Incomplete) => {
+                    if self.frame_len + self.buffer.len() > self.limits.max_buffer_len {
+                        return Err(DecodeError::QueryBufferLimit);
+                    }
+                    return Ok(None);
+                }
+                Err(error) => return Err(error),
+            };
+            let consumed = cursor.position() as usize;
+            self.buffer.advance(consumed);
+            self.frame_len += consumed;
+
+            let value = match token {
+                Token::Value(value) => value,
+                Token::Aggregate(value_type, bytes, remaining) => {
+                    if self.stack.len() >= self.limits.max_nesting_depth {
+                        return Err(DecodeError::Protocol(
+                            "aggregate nesting is too deep".to_string(),
+                        ));
+                    }
+                    // grow as elements arrive instead of trusting the announced size up front
+                    self.stack.push(PartialAggregate {
+                        value_type,
+                        bytes,
+                        remaining,
+                        children: vec![],
+                        attribute: None,
+                    });
+                    continue;
+                }
+            };
+            if let Some(resp) = self.complete(value) {
+                self.frame_len = 0;
+                return Ok(Some(resp));
+            }
+        }
+    }
+
+    /// Adds a decoded value to the aggregate it belongs to, closing every
+    /// aggregate it completes. Returns the frame once the outermost one closes.
+    fn complete(&mut self, mut value: RESP) -> Option<RESP> {
+        loop {
+            let attribute = match self.stack.last_mut() {
+                Some(parent) => &mut parent.attribute,
+                None => &mut self.attribute,
+            };
+            // an attribute is not an element itself but describes the next one
+            if value.value_type == Type::Attribute {
+                *attribute = Some(value);
+                return None;
+            }
+            if let Some(attribute) = attribute.take() {
+                value = value.with_attribute(attribute);
+            }
+
+            let parent = match self.stack.last_mut() {
+                Some(parent) => parent,
+                None => return Some(value),
+            };
+            parent.children.push(value);
+            parent.remaining -= 1;
+            if parent.remaining > 0 {
+                return None;
+            }
+            let parent = self.stack.pop()?;
+            value = RESP::new(parent.value_type, parent.bytes, parent.children);
+        }
+    }
+}
+
+/// Reads one value, or the header of an aggregate, from the front of the
+/// buffer. A top-level frame may also be an inline command such as `PING\r\n`
+/// sent by telnet sessions and health-check scripts.
+fn read_token(
+    cursor: &mut Cursor<&[u8]>,
+    limits: &DecoderLimits,
+    top_level: bool,
+) -> Result<Token> {
+    if top_level {
+        if let Some(&byte) = cursor.get_ref().first() {
+            if Type::try_from(char::from(byte)).is_err() {
+                return Ok(Token::Value(decode_inline(cursor, limits)?));
+            }
+        }
+    }
+
+    let data_type = read_data_type(cursor)?;
+    let value = match data_type {
+        Type::SimpleString | Type::Integer | Type::Error | Type::Double | Type::BigNumber => {
+            decode_line(cursor, data_type)?
+        }
+        Type::Null => decode_null(cursor)?,
+        Type::Boolean => decode_boolean(cursor)?,
+        Type::BulkString | Type::BulkError | Type::VerbatimString => {
+            decode_blob(cursor, limits, data_type)?
+        }
+        Type::Array | Type::Map | Type::Set | Type::Push | Type::Attribute => {
+            return read_aggregate_header(cursor, limits, data_type)
+        }
+    };
+    Ok(Token::Value(value))
+}
+
+/// Reads from a client request: an inline command, or a multibulk whose
+/// elements must all be bulk strings.
+fn read_request_token(
+    cursor: &mut Cursor<&[u8]>,
+    limits: &DecoderLimits,
+    top_level: bool,
+) -> Result<Token> {
+    let byte = match cursor.get_ref().first() {
+        Some(&byte) => byte,
+        None => return Err(DecodeError::Incomplete),
+    };
+    if top_level {
+        if byte != b'*' {
+            return Ok(Token::Value(decode_inline(cursor, limits)?));
+        }
+        cursor.advance(1);
+        let (bytes, array_size) =
+            read_length(cursor, limits, "mbulk count string", "multibulk length")?;
+        // an empty multibulk is skipped rather than rejected, like in Redis
+        if array_size <= 0 {
+            return Ok(Token::Value(RESP::new_array(vec![])));
+        }
+        if array_size as u64 > limits.max_multibulk_len as u64 {
+            return Err(DecodeError::Protocol(
+                "invalid multibulk length".to_string(),
+            ));
+        }
+        return Ok(Token::Aggregate(Type::Array, bytes, array_size as usize));
     }
 
-    fn decode_bulk_string(&mut self) -> Result<RESP> {
-        let bytes = self.read_until_crlf()?;
-        let string_count = String::from_utf8_lossy(&bytes[..])
-            .to_owned()
-            .parse::<usize>()?;
+    if byte != b'$' {
+        return Err(DecodeError::Protocol(format!(
+            "expected '$', got '{}'",
+            char::from(byte)
+        )));
+    }
+    cursor.advance(1);
+    let bulk = decode_blob(cursor, limits, Type::BulkString)?;
+    if bulk.is_null() {
+        return Err(DecodeError::Protocol("invalid bulk length".to_string()));
+    }
+    Ok(Token::Value(bulk))
+}
 
-        let mut string_bytes: Vec<u8> = vec![0; string_count + 2];
-        self.cursor.read(&mut string_bytes[..])?;
-        Ok(RESP::new(
-            Type::BulkString,
-            string_bytes[..string_bytes.len() - 2].to_vec(),
-            vec![],
-        ))
+fn decode_blob(
+    cursor: &mut Cursor<&[u8]>,
+    limits: &DecoderLimits,
+    data_type: Type,
+) -> Result<RESP> {
+    let (_, string_count) = read_length(cursor, limits, "bulk count string", "bulk length")?;
+    if string_count == -1 && data_type == Type::BulkString {
+        return Ok(RESP::new_null());
     }
+    let string_count = match usize::try_from(string_count) {
+        Ok(string_count) if string_count <= limits.max_bulk_len => string_count,
+        _ => return Err(DecodeError::Protocol("invalid bulk length".to_string())),
+    };
 
-    fn decode_integer(&mut self) -> Result<RESP> {
-        let bytes = self.read_until_crlf()?;
-        Ok(RESP::new(Type::Integer, bytes, vec![]))
+    if cursor.remaining() < string_count + 2 {
+        return Err(DecodeError::Incomplete);
     }
 
-    fn decode_error(&mut self) -> Result<RESP> {
-        let bytes = self.read_until_crlf()?;
-        Ok(RESP::new(Type::Error, bytes, vec![]))
+    let start = cursor.position() as usize;
+    let end = start + string_count;
+    let data = cursor.get_ref();
+    if &data[end..end + 2] != b"\r\n" {
+        return Err(DecodeError::Protocol(
+            "bulk string is not terminated by CRLF".to_string(),
+        ));
     }
 
-    fn decode_array(&mut self) -> Result<RESP> {
-        let bytes = self.read_until_crlf()?;
-        let array_size = String::from_utf8_lossy(&bytes[..])
-            .to_owned()
-            .parse::<usize>()?;
+    let string_bytes = Bytes::copy_from_slice(&data[start..end]);
+    cursor.advance(string_count + 2);
+    Ok(RESP::new(data_type, string_bytes, vec![]))
+}
 
-        let mut children: Vec<RESP> = vec![];
-        for _ in 0..array_size {
-            let child_resp = self.next_resp()?;
-            children.push(child_resp);
+fn read_aggregate_header(
+    cursor: &mut Cursor<&[u8]>,
+    limits: &DecoderLimits,
+    data_type: Type,
+) -> Result<Token> {
+    let (bytes, array_size) =
+        read_length(cursor, limits, "mbulk count string", "multibulk length")?;
+    if array_size == -1 && data_type == Type::Array {
+        return Ok(Token::Value(RESP::new_null_array()));
+    }
+    let array_size = match usize::try_from(array_size) {
+        Ok(array_size) if array_size <= limits.max_multibulk_len => array_size,
+        _ => {
+            return Err(DecodeError::Protocol(
+                "invalid multibulk length".to_string(),
+            ))
         }
+    };
+
+    // maps and attributes announce the number of pairs, not of elements
+    let element_count = match data_type {
+        Type::Map | Type::Attribute => array_size * 2,
+        _ => array_size,
+    };
+    if element_count == 0 {
+        return Ok(Token::Value(RESP::new(data_type, bytes, vec![])));
+    }
+    Ok(Token::Aggregate(data_type, bytes, element_count))
+}
 
-        Ok(RESP::new(Type::Array, bytes, children))
+fn decode_null(cursor: &mut Cursor<&[u8]>) -> Result<RESP> {
+    let bytes = read_until_crlf(cursor)?;
+    if !bytes.is_empty() {
+        return Err(DecodeError::Protocol(format!(
+            "invalid null: {}",
+            String::from_utf8_lossy(&bytes)
+        )));
     }
+    Ok(RESP::new(Type::Null, bytes, vec![]))
+}
+
+fn decode_boolean(cursor: &mut Cursor<&[u8]>) -> Result<RESP> {
+    let bytes = read_until_crlf(cursor)?;
+    if &bytes[..] != b"t" && &bytes[..] != b"f" {
+        return Err(DecodeError::Protocol(format!(
+            "invalid boolean: {}",
+            String::from_utf8_lossy(&bytes)
+        )));
+    }
+    Ok(RESP::new(Type::Boolean, bytes, vec![]))
+}
+
+fn decode_line(cursor: &mut Cursor<&[u8]>, data_type: Type) -> Result<RESP> {
+    let bytes = read_until_crlf(cursor)?;
+    Ok(RESP::new(data_type, bytes, vec![]))
+}
 
-    fn read_data_type(&mut self) -> Result<Type> {
-        let mut buffer = [0; 1];
-        self.cursor.read(&mut buffer)?;
-        let data_type_char = char::from(buffer[0]);
-        match Type::try_from(data_type_char) {
-            Ok(data_type) => Ok(data_type),
-            Err(e) => Err(anyhow!(e)),
+fn decode_inline(cursor: &mut Cursor<&[u8]>, limits: &DecoderLimits) -> Result<RESP> {
+    let start = cursor.position() as usize;
+    let data = cursor.get_ref();
+    let length = match data[start..].iter().position(|&byte| byte == b'\n') {
+        Some(length) => length,
+        None if data.len() - start > limits.max_inline_len => {
+            return Err(DecodeError::Protocol("too big inline request".to_string()))
         }
+        None => return Err(DecodeError::Incomplete),
+    };
+
+    let mut line = &data[start..start + length];
+    if line.last() == Some(&b'\r') {
+        line = &line[..line.len() - 1];
     }
 
-    pub fn read_until_crlf(&mut self) -> Result<Vec<u8>> {
-        let mut bytes: Vec<u8> = vec![];
+    let children = split_inline_args(line)?
+        .into_iter()
+        .map(|arg| RESP::new(Type::BulkString, arg, vec![]))
+        .collect::<Vec<RESP>>();
+    cursor.advance(length + 1);
+    Ok(RESP::new(Type::Array, children.len().to_string(), children))
+}
+
+/// Splits an inline command or config file line the way redis-cli does: arguments are
+/// separated by whitespace and may be wrapped in double quotes, which accept
+/// escape sequences, or in single quotes, which only accept `\'`.
+pub(crate) fn split_inline_args(line: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut args: Vec<Vec<u8>> = vec![];
+    let mut position = 0;
+    loop {
+        while position < line.len() && line[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if position == line.len() {
+            return Ok(args);
+        }
+
+        let mut arg: Vec<u8> = vec![];
+        let quote = match line[position] {
+            b'"' | b'\'' => Some(line[position]),
+            _ => None,
+        };
+        if quote.is_some() {
+            position += 1;
+        }
+
         loop {
-            let num_bytes = self.cursor.read_until(b'\n', &mut bytes)?;
-            if num_bytes > 2 && bytes[num_bytes - 2] == b'\r' {
-                break;
+            let byte = match line.get(position) {
+                Some(&byte) => byte,
+                None if quote.is_none() => break,
+                None => {
+                    return Err(DecodeError::Protocol(
+                        "unbalanced quotes in request".to_string(),
+                    ))
+                }
+            };
+
+            match quote {
+                None if byte.is_ascii_whitespace() => break,
+                None => arg.push(byte),
+                Some(quote) if byte == quote => {
+                    // a closing quote must be followed by a space or the end of line
+                    if matches!(line.get(position + 1), Some(next) if !next.is_ascii_whitespace()) {
+                        return Err(DecodeError::Protocol(
+                            "unbalanced quotes in request".to_string(),
+                        ));
+                    }
+                    position += 1;
+                    break;
+                }
+                Some(b'"') if byte == b'\\' && position + 1 < line.len() => {
+                    position += 1;
+                    let escaped = line[position];
+                    let hex_value = line
+                        .get(position + 1..position + 3)
+                        .filter(|digits| digits.iter().all(|digit| digit.is_ascii_hexdigit()))
+                        .and_then(|digits| std::str::from_utf8(digits).ok())
+                        .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+                    match (escaped, hex_value) {
+                        (b'x', Some(value)) => {
+                            arg.push(value);
+                            position += 2;
+                        }
+                        (b'n', _) => arg.push(b'\n'),
+                        (b'r', _) => arg.push(b'\r'),
+                        (b't', _) => arg.push(b'\t'),
+                        (b'b', _) => arg.push(0x08),
+                        (b'a', _) => arg.push(0x07),
+                        (other, _) => arg.push(other),
+                    }
+                }
+                Some(b'\'') if byte == b'\\' && line.get(position + 1) == Some(&b'\'') => {
+                    position += 1;
+                    arg.push(b'\'');
+                }
+                Some(_) => arg.push(byte),
             }
+            position += 1;
         }
 
-        Ok(bytes[..bytes.len() - 2].to_vec())
+        args.push(arg);
+    }
+}
+
+fn read_data_type(cursor: &mut Cursor<&[u8]>) -> Result<Type> {
+    if !cursor.has_remaining() {
+        return Err(DecodeError::Incomplete);
+    }
+
+    let data_type_char = char::from(cursor.get_u8());
+    match Type::try_from(data_type_char) {
+        Ok(data_type) => Ok(data_type),
+        Err(_) => Err(DecodeError::Protocol(format!(
+            "expected '$', got '{}'",
+            data_type_char
+        ))),
+    }
+}
+
+/// Reads the length line of a bulk string or aggregate. A line that grows past
+/// the inline limit without a CRLF is rejected rather than buffered forever.
+fn read_length(
+    cursor: &mut Cursor<&[u8]>,
+    limits: &DecoderLimits,
+    too_big: &str,
+    invalid: &str,
+) -> Result<(Bytes, i64)> {
+    let bytes = match read_until_crlf(cursor) {
+        Err(DecodeError::Incomplete) if cursor.remaining() > limits.max_inline_len => {
+            return Err(DecodeError::Protocol(format!("too big {}", too_big)));
+        }
+        result => result?,
+    };
+    match std::str::from_utf8(&bytes)
+        .ok()
+        .and_then(|length| length.parse::<i64>().ok())
+    {
+        Some(length) => Ok((bytes, length)),
+        None => Err(DecodeError::Protocol(format!("invalid {}", invalid))),
+    }
+}
+
+fn read_until_crlf(cursor: &mut Cursor<&[u8]>) -> Result<Bytes> {
+    let start = cursor.position() as usize;
+    let data = cursor.get_ref();
+    match data[start..]
+        .windows(2)
+        .position(|window| window == b"\r\n")
+    {
+        Some(length) => {
+            let bytes = Bytes::copy_from_slice(&data[start..start + length]);
+            cursor.advance(length + 2);
+            Ok(bytes)
+        }
+        None => Err(DecodeError::Incomplete),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{RESPDecoder, Type};
+    use super::{DecodeError, DecoderLimits, RESPDecoder, Type};
 
     #[test]
     fn it_should_decode_simple_string() {
         let simple_string = b"+OK\r\n";
         let resp = RESPDecoder::new(simple_string.to_vec())
             .next_resp()
+            .unwrap()
             .unwrap();
         assert_eq!(resp.string(), "OK");
     }
@@ -109,28 +542,50 @@ mod tests {
     #[test]
     fn it_should_decode_bulk_string() {
         let bulk_string = b"$5\r\nhello\r\n";
-        let resp = RESPDecoder::new(bulk_string.to_vec()).next_resp().unwrap();
+        let resp = RESPDecoder::new(bulk_string.to_vec())
+            .next_resp()
+            .unwrap()
+            .unwrap();
         assert_eq!(resp.string(), "hello");
     }
 
+    #[test]
+    fn it_should_decode_binary_bulk_string() {
+        let bulk_string = b"$4\r\n\x00\xff\r\n\r\n";
+        let resp = RESPDecoder::new(bulk_string.to_vec())
+            .next_resp()
+            .unwrap()
+            .unwrap();
+        assert_eq!(&resp.bytes()[..], b"\x00\xff\r\n");
+    }
+
     #[test]
     fn it_should_decode_integer_string() {
         let integer = b":1000\r\n";
-        let resp = RESPDecoder::new(integer.to_vec()).next_resp().unwrap();
-        assert_eq!(resp.integer(), 1000);
+        let resp = RESPDecoder::new(integer.to_vec())
+            .next_resp()
+            .unwrap()
+            .unwrap();
+        assert_eq!(resp.integer(), Some(1000));
     }
 
     #[test]
     fn it_should_decode_error_string() {
         let error = b"-ERR unknown command 'helloworld'\r\n";
-        let resp = RESPDecoder::new(error.to_vec()).next_resp().unwrap();
+        let resp = RESPDecoder::new(error.to_vec())
+            .next_resp()
+            .unwrap()
+            .unwrap();
         assert_eq!(resp.string(), "ERR unknown command 'helloworld'");
     }
 
     #[test]
     fn it_should_decode_array_string() {
         let error = b"*2\r\n$5\r\nhello\r\n$5\r\nworld\r\n";
-        let resp = RESPDecoder::new(error.to_vec()).next_resp().unwrap();
+        let resp = RESPDecoder::new(error.to_vec())
+            .next_resp()
+            .unwrap()
+            .unwrap();
         let children = resp.array();
         assert_eq!(children.len(), 2);
         assert_eq!(children[0].string(), "hello");
@@ -141,7 +596,10 @@ mod tests {
     fn it_should_decode_nested_array_string() {
         let error = b"*2\r\n*3\r\n:1\r\n:2\r\n:3\r\n*2\r\n+Hello\r\n-World\r\n";
 
-        let resp = RESPDecoder::new(error.to_vec()).next_resp().unwrap();
+        let resp = RESPDecoder::new(error.to_vec())
+            .next_resp()
+            .unwrap()
+            .unwrap();
         assert_eq!(resp.value_type, Type::Array);
 
         let children = resp.array();
@@ -153,10 +611,314 @@ mod tests {
         assert_eq!(second_child.value_type, Type::Array);
         let first_child_array = first_child.array();
         let second_child_array = second_child.array();
-        assert_eq!(first_child_array[0].integer(), 1);
-        assert_eq!(first_child_array[1].integer(), 2);
-        assert_eq!(first_child_array[2].integer(), 3);
+        assert_eq!(first_child_array[0].integer(), Some(1));
+        assert_eq!(first_child_array[1].integer(), Some(2));
+        assert_eq!(first_child_array[2].integer(), Some(3));
         assert_eq!(second_child_array[0].string(), "Hello");
         assert_eq!(second_child_array[1].string(), "World");
     }
+
+    #[test]
+    fn it_should_decode_null_bulk_string_and_null_array() {
+        for null in [&b"$-1\r\n"[..], &b"*-1\r\n"[..], &b"_\r\n"[..]] {
+            let resp = RESPDecoder::new(null.to_vec())
+                .next_resp()
+                .unwrap()
+                .unwrap();
+            assert!(resp.is_null());
+        }
+    }
+
+    #[test]
+    fn it_should_decode_resp3_scalars() {
+        let mut decoder = RESPDecoder::new(
+            b"#t\r\n#f\r\n,3.25\r\n,-inf\r\n(3492890328409238509324850943850943825024385\r\n\
+              !21\r\nSYNTAX invalid syntax\r\n=15\r\ntxt:Some string\r\n"
+                .to_vec(),
+        );
+
+        assert!(decoder.next_resp().unwrap().unwrap().boolean());
+        assert!(!decoder.next_resp().unwrap().unwrap().boolean());
+        assert_eq!(decoder.next_resp().unwrap().unwrap().double(), Some(3.25));
+        assert_eq!(
+            decoder.next_resp().unwrap().unwrap().double(),
+            Some(f64::NEG_INFINITY)
+        );
+
+        let big_number = decoder.next_resp().unwrap().unwrap();
+        assert_eq!(big_number.value_type, Type::BigNumber);
+        assert_eq!(
+            big_number.string(),
+            "3492890328409238509324850943850943825024385"
+        );
+
+        let bulk_error = decoder.next_resp().unwrap().unwrap();
+        assert_eq!(bulk_error.value_type, Type::BulkError);
+        assert_eq!(bulk_error.string(), "SYNTAX invalid syntax");
+
+        let verbatim = decoder.next_resp().unwrap().unwrap();
+        assert_eq!(verbatim.value_type, Type::VerbatimString);
+        assert_eq!(verbatim.string(), "txt:Some string");
+    }
+
+    #[test]
+    fn it_should_decode_resp3_aggregates() {
+        let map = b"%2\r\n+first\r\n:1\r\n+second\r\n~2\r\n:2\r\n:3\r\n";
+        let resp = RESPDecoder::new(map.to_vec()).next_resp().unwrap().unwrap();
+        assert_eq!(resp.value_type, Type::Map);
+
+        let pairs = resp.pairs();
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(pairs[0].0.string(), "first");
+        assert_eq!(pairs[0].1.integer(), Some(1));
+        assert_eq!(pairs[1].1.value_type, Type::Set);
+        assert_eq!(pairs[1].1.array().len(), 2);
+
+        let push = b">2\r\n+message\r\n$5\r\nhello\r\n";
+        let resp = RESPDecoder::new(push.to_vec())
+            .next_resp()
+            .unwrap()
+            .unwrap();
+        assert_eq!(resp.value_type, Type::Push);
+        assert_eq!(resp.array()[1].string(), "hello");
+    }
+
+    #[test]
+    fn it_should_attach_attribute_to_the_following_value() {
+        let frame = b"*2\r\n|1\r\n+ttl\r\n:3600\r\n:2039123\r\n:9543892\r\n";
+        let resp = RESPDecoder::new(frame.to_vec())
+            .next_resp()
+            .unwrap()
+            .unwrap();
+
+        let children = resp.array();
+        assert_eq!(children.len(), 2);
+        assert_eq!(children[0].integer(), Some(2039123));
+        assert_eq!(children[1].integer(), Some(9543892));
+
+        let attribute = children[0].attribute().unwrap();
+        assert_eq!(attribute.value_type, Type::Attribute);
+        assert_eq!(attribute.pairs()[0].0.string(), "ttl");
+        assert_eq!(attribute.pairs()[0].1.integer(), Some(3600));
+    }
+
+    #[test]
+    fn it_should_decode_inline_command_like_multibulk() {
+        let inline = RESPDecoder::new(b"SET key \"hello world\"\r\n".to_vec())
+            .next_resp()
+            .unwrap()
+            .unwrap();
+        let multibulk =
+            RESPDecoder::new(b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$11\r\nhello world\r\n".to_vec())
+                .next_resp()
+                .unwrap()
+                .unwrap();
+        assert_eq!(inline, multibulk);
+    }
+
+    #[test]
+    fn it_should_decode_inline_quoting_and_escapes() {
+        let tests: [(&[u8], Vec<&[u8]>); 5] = [
+            (b"PING\n", vec![b"PING"]),
+            (b"  echo   a  b \r\n", vec![b"echo", b"a", b"b"]),
+            (b"echo \"a\\tb\\x41\\\"\"\r\n", vec![b"echo", b"a\tbA\""]),
+            (b"echo 'it\\'s' \"\"\r\n", vec![b"echo", b"it's", b""]),
+            (b"\r\n", vec![]),
+        ];
+
+        for (line, expected) in tests {
+            let resp = RESPDecoder::new(line.to_vec())
+                .next_resp()
+                .unwrap()
+                .unwrap();
+            let args: Vec<&[u8]> = resp.array().iter().map(|arg| &arg.bytes()[..]).collect();
+            assert_eq!(args, expected);
+        }
+    }
+
+    #[test]
+    fn it_should_reject_unbalanced_inline_quotes() {
+        for line in [&b"echo \"hello\r\n"[..], &b"echo \"a\"b\r\n"[..]] {
+            let error = RESPDecoder::new(line.to_vec()).next_resp().unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "Protocol error: unbalanced quotes in request"
+            );
+        }
+    }
+
+    #[test]
+    fn it_should_reject_frames_that_break_the_limits() {
+        let limits = DecoderLimits {
+            max_bulk_len: 16,
+            max_multibulk_len: 4,
+            max_inline_len: 32,
+            max_buffer_len: 1024,
+            max_nesting_depth: 4,
+        };
+        let tests: [(Vec<u8>, &str); 7] = [
+            (
+                b"*1\r\n$17\r\n".to_vec(),
+                "Protocol error: invalid bulk length",
+            ),
+            (
+                b"*1\r\n$-5\r\n".to_vec(),
+                "Protocol error: invalid bulk length",
+            ),
+            (
+                b"*1\r\n$abc\r\n".to_vec(),
+                "Protocol error: invalid bulk length",
+            ),
+            (
+                b"*5\r\n".to_vec(),
+                "Protocol error: invalid multibulk length",
+            ),
+            (
+                [&b"*"[..], &[b'1'; 40]].concat(),
+                "Protocol error: too big mbulk count string",
+            ),
+            (vec![b'a'; 40], "Protocol error: too big inline request"),
+            (
+                b"*1\r\n?\r\n".to_vec(),
+                "Protocol error: expected '$', got '?'",
+            ),
+        ];
+
+        for (frame, expected) in tests {
+            let mut decoder = RESPDecoder::new(frame);
+            decoder.set_limits(limits);
+            let error = decoder.next_resp().unwrap_err();
+            assert_eq!(error.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn it_should_only_accept_bulk_strings_in_a_request() {
+        let nested = b"*1\r\n".repeat(100_000);
+        let tests: [(&[u8], &str); 4] = [
+            (&nested, "Protocol error: expected '$', got '*'"),
+            (b"*1\r\n:1\r\n", "Protocol error: expected '$', got ':'"),
+            (b"*1\r\n$-1\r\n", "Protocol error: invalid bulk length"),
+            (
+                b"*2\r\n$4\r\necho\r\n%1\r\n",
+                "Protocol error: expected '$', got '%'",
+            ),
+        ];
+
+        for (frame, expected) in tests {
+            let error = RESPDecoder::new(frame.to_vec()).next_request().unwrap_err();
+            assert_eq!(error.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn it_should_decode_requests() {
+        let mut decoder =
+            RESPDecoder::new(b"*0\r\n+PING\r\n*2\r\n$4\r\necho\r\n$2\r\nhi\r\n".to_vec());
+        assert!(decoder.next_request().unwrap().unwrap().array().is_empty());
+        // outside a multibulk everything is an inline command
+        assert_eq!(
+            decoder.next_request().unwrap().unwrap().array()[0].string(),
+            "+PING"
+        );
+        assert_eq!(
+            decoder.next_request().unwrap().unwrap().array()[1].string(),
+            "hi"
+        );
+    }
+
+    #[test]
+    fn it_should_limit_the_nesting_of_a_reply() {
+        let nested = b"*1\r\n".repeat(100_000);
+        let error = RESPDecoder::new(nested).next_resp().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Protocol error: aggregate nesting is too deep"
+        );
+    }
+
+    #[test]
+    fn it_should_reject_a_query_buffer_over_the_limit() {
+        let mut decoder = RESPDecoder::new(b"*1\r\n$16\r\n".to_vec());
+        decoder.set_limits(DecoderLimits {
+            max_bulk_len: 16,
+            max_multibulk_len: 4,
+            max_inline_len: 32,
+            max_buffer_len: 16,
+            max_nesting_depth: 4,
+        });
+        assert!(decoder.next_resp().unwrap().is_none());
+
+        decoder.extend(b"0123456789");
+        let error = decoder.next_resp().unwrap_err();
+        assert_eq!(error, DecodeError::QueryBufferLimit);
+    }
+
+    #[test]
+    fn it_should_wait_for_the_end_of_an_inline_command() {
+        let mut decoder = RESPDecoder::new(b"PI".to_vec());
+        assert!(decoder.next_resp().unwrap().is_none());
+
+        decoder.extend(b"NG\r\n");
+        let resp = decoder.next_resp().unwrap().unwrap();
+        assert_eq!(resp.array()[0].string(), "PING");
+    }
+
+    #[test]
+    fn it_should_wait_for_the_rest_of_a_split_frame() {
+        let mut decoder = RESPDecoder::new(b"*2\r\n$4\r\necho\r\n$5\r\nhel".to_vec());
+        assert!(decoder.next_resp().unwrap().is_none());
+
+        decoder.extend(b"lo\r");
+        assert!(decoder.next_resp().unwrap().is_none());
+
+        decoder.extend(b"\n");
+        let resp = decoder.next_resp().unwrap().unwrap();
+        assert_eq!(resp.array()[1].string(), "hello");
+        assert!(!decoder.has_remaining());
+    }
+
+    #[test]
+    fn it_should_keep_decoded_elements_of_a_partial_frame() {
+        let mut decoder = RESPDecoder::new(b"*3\r\n$1\r\na\r\n*2\r\n:1\r\n$5\r\nhe".to_vec());
+        assert!(decoder.next_resp().unwrap().is_none());
+        // only the element that is still incomplete is left to decode again
+        assert_eq!(&decoder.buffer[..], b"$5\r\nhe");
+
+        decoder.extend(b"llo\r\n:2\r\n");
+        let resp = decoder.next_resp().unwrap().unwrap();
+        assert_eq!(resp.array()[0].string(), "a");
+        assert_eq!(resp.array()[1].array()[1].string(), "hello");
+        assert_eq!(resp.array()[2].integer(), Some(2));
+        assert!(!decoder.has_remaining());
+    }
+
+    #[test]
+    fn it_should_decode_bulk_string_larger_than_a_single_read() {
+        let value = "x".repeat(4096);
+        let frame = format!("${}\r\n{}\r\n", value.len(), value);
+        let mut decoder = RESPDecoder::new(vec![]);
+        for chunk in frame.as_bytes().chunks(1024) {
+            assert!(decoder.next_resp().unwrap().is_none());
+            decoder.extend(chunk);
+        }
+
+        let resp = decoder.next_resp().unwrap().unwrap();
+        assert_eq!(resp.string(), value);
+    }
+
+    #[test]
+    fn it_should_decode_pipelined_commands() {
+        let commands = b"*1\r\n$4\r\nping\r\n*2\r\n$4\r\necho\r\n$5\r\nhello\r\n";
+        let mut decoder = RESPDecoder::new(commands.to_vec());
+
+        let first = decoder.next_resp().unwrap().unwrap();
+        assert_eq!(first.array()[0].string(), "ping");
+        assert!(decoder.has_remaining());
+
+        let second = decoder.next_resp().unwrap().unwrap();
+        assert_eq!(second.array()[0].string(), "echo");
+        assert_eq!(second.array()[1].string(), "hello");
+        assert!(!decoder.has_remaining());
+    }
 }
@@ -0,0 +1,201 @@
+use crate::resp::{
+    ProtocolVersion, Type, ARRAY, BULK_STRING, CRLF, ERROR, INTEGER, NULL_ARRAY, NULL_STRING, RESP,
+    RESP3_NULL,
+};
+
+/// Serializes `RESP` values for a connection speaking the given protocol.
+///
+/// RESP3 values are sent as-is to RESP3 clients. RESP2 clients get the closest
+/// RESP2 shape instead: maps become flat arrays, sets and pushes become arrays,
+/// booleans become integers and the remaining scalars become bulk strings.
+pub struct RESPEncoder {
+    protocol: ProtocolVersion,
+}
+
+impl RESPEncoder {
+    pub fn new(protocol: ProtocolVersion) -> Self {
+        RESPEncoder { protocol }
+    }
+
+    pub fn encode(&self, resp: &RESP) -> Vec<u8> {
+        let mut bytes: Vec<u8> = vec![];
+        self.encode_into(resp, &mut bytes);
+        bytes
+    }
+
+    pub fn encode_into(&self, resp: &RESP, bytes: &mut Vec<u8>) {
+        match self.protocol {
+            ProtocolVersion::RESP2 => self.encode_resp2(resp, bytes),
+            ProtocolVersion::RESP3 => self.encode_resp3(resp, bytes),
+        }
+    }
+
+    fn encode_resp2(&self, resp: &RESP, bytes: &mut Vec<u8>) {
+        match resp.value_type {
+            Type::SimpleString | Type::Error | Type::Integer => {
+                append_line(bytes, resp.value_type.as_char(), resp.bytes())
+            }
+            Type::BulkString | Type::Double | Type::BigNumber => {
+                append_blob(bytes, BULK_STRING, resp.bytes())
+            }
+            Type::VerbatimString => append_blob(bytes, BULK_STRING, verbatim_text(resp)),
+            Type::BulkError => append_line(bytes, ERROR, resp.bytes()),
+            Type::Boolean => {
+                let value = if resp.boolean() { b"1" } else { b"0" };
+                append_line(bytes, INTEGER, value)
+            }
+            Type::Null => bytes.extend_from_slice(NULL_STRING.as_bytes()),
+            Type::Array if resp.is_null_array() => bytes.extend_from_slice(NULL_ARRAY.as_bytes()),
+            Type::Array | Type::Map | Type::Set | Type::Push => {
+                append_header(bytes, ARRAY, resp.array().len());
+                for child in resp.array() {
+                    self.encode_resp2(child, bytes);
+                }
+            }
+            // RESP2 has no way to express out-of-band metadata
+            Type::Attribute => {}
+        }
+    }
+
+    fn encode_resp3(&self, resp: &RESP, bytes: &mut Vec<u8>) {
+        if let Some(attribute) = resp.attribute() {
+            self.encode_resp3(attribute, bytes);
+        }
+
+        let type_char = resp.value_type.as_char();
+        match resp.value_type {
+            Type::SimpleString
+            | Type::Error
+            | Type::Integer
+            | Type::Boolean
+            | Type::Double
+            | Type::BigNumber => append_line(bytes, type_char, resp.bytes()),
+            Type::BulkString | Type::BulkError | Type::VerbatimString => {
+                append_blob(bytes, type_char, resp.bytes())
+            }
+            Type::Null => bytes.extend_from_slice(RESP3_NULL.as_bytes()),
+            Type::Array if resp.is_null_array() => bytes.extend_from_slice(RESP3_NULL.as_bytes()),
+            Type::Array | Type::Set | Type::Push => {
+                append_header(bytes, type_char, resp.array().len());
+                for child in resp.array() {
+                    self.encode_resp3(child, bytes);
+                }
+            }
+            Type::Map | Type::Attribute => {
+                append_header(bytes, type_char, resp.array().len() / 2);
+                for child in resp.array() {
+                    self.encode_resp3(child, bytes);
+                }
+            }
+        }
+    }
+}
+
+fn append_line(bytes: &mut Vec<u8>, type_char: char, value: &[u8]) {
+    bytes.push(type_char as u8);
+    bytes.extend_from_slice(value);
+    bytes.extend_from_slice(CRLF.as_bytes());
+}
+
+fn append_blob(bytes: &mut Vec<u8>, type_char: char, value: &[u8]) {
+    append_header(bytes, type_char, value.len());
+    bytes.extend_from_slice(value);
+    bytes.extend_from_slice(CRLF.as_bytes());
+}
+
+fn append_header(bytes: &mut Vec<u8>, type_char: char, len: usize) {
+    bytes.push(type_char as u8);
+    bytes.extend_from_slice(len.to_string().as_bytes());
+    bytes.extend_from_slice(CRLF.as_bytes());
+}
+
+/// Strips the `txt:` style format prefix that only RESP3 can carry.
+fn verbatim_text(resp: &RESP) -> &[u8] {
+    let data = &resp.bytes()[..];
+    if data.len() >= 4 && data[3] == b':' {
+        &data[4..]
+    } else {
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RESPEncoder;
+    use crate::resp::{ProtocolVersion, RESP};
+    use crate::resp_decoder::RESPDecoder;
+
+    #[test]
+    fn it_should_round_trip_every_type_through_the_decoder() {
+        let resp = RESP::new_array(vec![
+            RESP::new_simple_string("OK"),
+            RESP::new_error("ERR", "something went wrong"),
+            RESP::new_integer(-42),
+            RESP::new_bulk_string(&b"\x00binary\r\n"[..]),
+            RESP::new_null(),
+            RESP::new_array(vec![
+                RESP::new_array(vec![RESP::new_integer(1), RESP::new_integer(2)]),
+                RESP::new_array(vec![]),
+            ]),
+            RESP::new_boolean(true),
+            RESP::new_double(1.5),
+            RESP::new_double(f64::INFINITY),
+            RESP::new_big_number("3492890328409238509324850943850943825024385"),
+            RESP::new_bulk_error("SYNTAX", "invalid syntax"),
+            RESP::new_verbatim_string("txt", b"Some string"),
+            RESP::new_map(vec![(
+                RESP::new_bulk_string("key"),
+                RESP::new_set(vec![RESP::new_integer(1)]),
+            )]),
+            RESP::new_push(vec![RESP::new_bulk_string("message")]),
+            RESP::new_integer(7).with_attribute(RESP::new_attribute(vec![(
+                RESP::new_simple_string("ttl"),
+                RESP::new_integer(3600),
+            )])),
+        ]);
+
+        let bytes = RESPEncoder::new(ProtocolVersion::RESP3).encode(&resp);
+        let decoded = RESPDecoder::new(bytes).next_resp().unwrap().unwrap();
+        assert_eq!(decoded, resp);
+    }
+
+    #[test]
+    fn it_should_round_trip_resp2_types_through_the_decoder() {
+        let resp = RESP::new_array(vec![
+            RESP::new_simple_string("OK"),
+            RESP::new_integer(1),
+            RESP::new_bulk_string("hello"),
+            RESP::new_null(),
+            RESP::new_null_array(),
+            RESP::new_array(vec![RESP::new_array(vec![RESP::new_integer(3)])]),
+        ]);
+
+        let bytes = RESPEncoder::new(ProtocolVersion::RESP2).encode(&resp);
+        assert_eq!(
+            String::from_utf8_lossy(&bytes),
+            "*6\r\n+OK\r\n:1\r\n$5\r\nhello\r\n$-1\r\n*-1\r\n*1\r\n*1\r\n:3\r\n"
+        );
+        let decoded = RESPDecoder::new(bytes).next_resp().unwrap().unwrap();
+        assert_eq!(decoded, resp);
+    }
+
+    #[test]
+    fn it_should_downgrade_resp3_types_for_resp2_clients() {
+        let tests = [
+            (
+                RESP::new_map(vec![(RESP::new_bulk_string("a"), RESP::new_integer(1))]),
+                "*2\r\n$1\r\na\r\n:1\r\n",
+            ),
+            (RESP::new_set(vec![RESP::new_integer(1)]), "*1\r\n:1\r\n"),
+            (RESP::new_boolean(false), ":0\r\n"),
+            (RESP::new_double(0.25), "$4\r\n0.25\r\n"),
+            (RESP::new_verbatim_string("txt", b"hi"), "$2\r\nhi\r\n"),
+            (RESP::new_null_array(), "*-1\r\n"),
+        ];
+
+        for (resp, expected) in tests {
+            let bytes = RESPEncoder::new(ProtocolVersion::RESP2).encode(&resp);
+            assert_eq!(String::from_utf8_lossy(&bytes), expected);
+        }
+    }
+}
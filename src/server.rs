@@ -0,0 +1,85 @@
+use crate::clock::{Clock, SystemClock};
+use crate::config::Config;
+use crate::store::Store;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::sync::watch;
+
+/// State shared by every connection of a running server.
+pub struct Server {
+    pub clock: Arc<dyn Clock>,
+    pub store: RwLock<Store>,
+    pub config: RwLock<Config>,
+    pub stats: Arc<Stats>,
+    shutdown: watch::Sender<bool>,
+}
+
+impl Server {
+    pub fn new(config: Config) -> Self {
+        Server::with_clock(config, Arc::new(SystemClock::new()))
+    }
+
+    /// A server whose keys expire by `clock`, e.g. a `ManualClock` in tests.
+    pub fn with_clock(config: Config, clock: Arc<dyn Clock>) -> Self {
+        let stats = Arc::new(Stats::default());
+        Server {
+            clock: Arc::clone(&clock),
+            store: RwLock::new(Store::with_stats(clock, Arc::clone(&stats))),
+            config: RwLock::new(config),
+            stats,
+            shutdown: watch::channel(false).0,
+        }
+    }
+
+    /// Asks the listeners to stop accepting and idle connections to close.
+    pub fn shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
+    /// Resolves once `shutdown` has been called, immediately if it already was.
+    pub async fn shutdown_requested(&self) {
+        let mut receiver = self.shutdown.subscribe();
+        while !*receiver.borrow_and_update() {
+            if receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Counters reported by the server. `CONFIG RESETSTAT` zeroes all of them
+/// except `connected_clients`, which tracks live connections.
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub connected_clients: AtomicU64,
+    pub total_connections_received: AtomicU64,
+    pub rejected_connections: AtomicU64,
+    pub total_commands_processed: AtomicU64,
+    pub keyspace_hits: AtomicU64,
+    pub keyspace_misses: AtomicU64,
+    /// Keys deleted because they expired, on access or by the active cycle.
+    pub expired_keys: AtomicU64,
+}
+
+impl Stats {
+    pub fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        for counter in [
+            &self.total_connections_received,
+            &self.rejected_connections,
+            &self.total_commands_processed,
+            &self.keyspace_hits,
+            &self.keyspace_misses,
+            &self.expired_keys,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
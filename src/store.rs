@@ -1,90 +1,487 @@
+use crate::clock::{Clock, SystemClock};
+use crate::dict::Dict;
+use crate::server::Stats;
+use bytes::Bytes;
 use std::collections::HashMap;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Keys sampled per round of the active expire cycle.
+const ACTIVE_EXPIRE_KEYS_PER_LOOP: usize = 20;
+/// The active expire cycle samples again while more than this percentage of
+/// the last sample had expired.
+const ACTIVE_EXPIRE_ACCEPTABLE_STALE: usize = 10;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct StoreValue {
-    value: String,
+    // a growable buffer so APPEND and SETRANGE can write in place
+    value: Vec<u8>,
     expired_at: Option<u128>,
 }
 
 impl StoreValue {
-    pub fn new(value: String, expired_at: Option<u128>) -> Self {
+    pub fn new(value: Vec<u8>, expired_at: Option<u128>) -> Self {
         StoreValue { value, expired_at }
     }
-}
 
-#[derive(Debug, Clone, PartialEq)]
+    fn is_expired(&self, now: u128) -> bool {
+        matches!(self.expired_at, Some(expired_at) if expired_at < now)
+    }
+}
 
+#[derive(Debug, Clone)]
 pub struct Store {
-    map: HashMap<String, StoreValue>,
+    clock: Arc<dyn Clock>,
+    map: Dict<StoreValue>,
+    volatile: VolatileKeys,
+    rng: XorShift,
+    stats: Arc<Stats>,
+}
+
+impl Default for Store {
+    fn default() -> Self {
+        Store::new()
+    }
 }
 
 impl Store {
     pub fn new() -> Self {
+        Store::with_clock(Arc::new(SystemClock::new()))
+    }
+
+    /// A store that expires keys by `clock`.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Store::with_stats(clock, Arc::default())
+    }
+
+    /// A store that counts the keys it expires in `stats`.
+    pub fn with_stats(clock: Arc<dyn Clock>, stats: Arc<Stats>) -> Self {
+        let seed = clock.now() as u64;
         Store {
-            map: HashMap::new(),
+            clock,
+            map: Dict::new(),
+            volatile: VolatileKeys::default(),
+            rng: XorShift::new(seed),
+            stats,
         }
     }
 
-    pub fn set(&mut self, key: String, value: String, options: &SetOptions) -> Option<StoreValue> {
-        let current_expired_at = match self.map.get(&key) {
-            Some(store_value) => store_value.expired_at,
-            None => None,
+    /// Sets `key` unless `options` has a condition that does not hold. Without
+    /// `KEEPTTL` the key loses any TTL it had, like in Redis.
+    pub fn set(&mut self, key: Bytes, value: Bytes, options: &SetOptions) -> SetOutcome {
+        let previous = self.get(&key);
+        let written = match options.condition {
+            SetCondition::Always => true,
+            SetCondition::IfNotExists => previous.is_none(),
+            SetCondition::IfExists => previous.is_some(),
         };
+        if !written {
+            return SetOutcome { written, previous };
+        }
 
-        let expired_at = match options.expire {
-            Some(expire) => Some(
-                get_unixtime()
-                    .checked_add(Duration::from_millis(expire))
-                    .unwrap()
-                    .as_millis(),
-            ),
-            None => current_expired_at,
+        let expired_at = match options.expiration {
+            Some(Expiration::At(expired_at)) => Some(expired_at),
+            Some(Expiration::KeepTtl) if previous.is_some() => self
+                .map
+                .get(&key)
+                .and_then(|store_value| store_value.expired_at),
+            Some(Expiration::KeepTtl) | None => None,
         };
-        let store_value = StoreValue { value, expired_at };
-        self.map.insert(key, store_value)
+        self.insert_value(
+            key,
+            StoreValue {
+                value: value.to_vec(),
+                expired_at,
+            },
+        );
+        SetOutcome { written, previous }
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> Option<Bytes> {
+        self.lookup(key)
+            .map(|store_value| Bytes::copy_from_slice(&store_value.value))
+    }
+
+    /// The value of `key` to change in place, keeping its TTL.
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut Vec<u8>> {
+        self.lookup_mut(key)
+            .map(|store_value| &mut store_value.value)
+    }
+
+    /// Like `get_mut`, first creating `key` as an empty string without a TTL
+    /// if it does not exist.
+    pub fn get_or_insert_mut(&mut self, key: &Bytes) -> &mut Vec<u8> {
+        if self.lookup(key).is_none() {
+            self.insert_value(key.clone(), StoreValue::new(vec![], None));
+        }
+        &mut self
+            .map
+            .get_mut(key)
+            .expect("the key was just inserted")
+            .value
+    }
+
+    pub fn contains_key(&mut self, key: &[u8]) -> bool {
+        self.lookup(key).is_some()
+    }
+
+    /// Looks up a key, deleting it first if it has expired.
+    fn lookup(&mut self, key: &[u8]) -> Option<&StoreValue> {
+        if self.map.get(key)?.is_expired(self.clock.now()) {
+            self.remove_value(key);
+            Stats::increment(&self.stats.expired_keys);
+            return None;
+        }
+        self.map.get(key)
+    }
+
+    /// Like `lookup`, for changing the value or its TTL in place.
+    fn lookup_mut(&mut self, key: &[u8]) -> Option<&mut StoreValue> {
+        self.lookup(key)?;
+        self.map.get_mut(key)
+    }
+
+    /// Deletes `key` and returns its value, or `None` if it had expired.
+    pub fn remove(&mut self, key: &[u8]) -> Option<Bytes> {
+        let value = self.get(key);
+        self.remove_value(key);
+        value
     }
 
-    pub fn get(&self, key: String) -> Option<String> {
-        match self.map.get(&key) {
+    /// Moves the value of `key` and its TTL to `new_key`, replacing whatever
+    /// `new_key` held. Returns whether `key` existed.
+    pub fn rename(&mut self, key: &[u8], new_key: Bytes) -> bool {
+        if !self.contains_key(key) {
+            return false;
+        }
+        match self.remove_value(key) {
             Some(store_value) => {
-                match store_value.expired_at {
-                    Some(expired_at) => {
-                        let current_unixtime = get_unixtime().as_millis();
-                        if expired_at < current_unixtime {
-                            // TODO: mapから値を削除する
-                            //       使う側でRwLockを使用しているため、可変参照にできない
-                            //       jobで定期的に期限切れの値を削除する仕様であれば、ここで削除する必要はないかも？
-                            return None;
-                        } else {
-                            return Some(store_value.value.to_owned());
-                        }
-                    }
-                    None => Some(store_value.value.to_owned()),
-                };
-
-                Some(store_value.value.to_owned())
+                self.insert_value(new_key, store_value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Copies the value of `key` and its TTL to `new_key`, replacing whatever
+    /// `new_key` held. Returns whether `key` existed.
+    pub fn copy(&mut self, key: &[u8], new_key: Bytes) -> bool {
+        match self.lookup(key) {
+            Some(store_value) => {
+                let store_value = store_value.clone();
+                self.insert_value(new_key, store_value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The Unix time in milliseconds `key` expires at: `None` if the key does
+    /// not exist and `Some(None)` if it has no TTL.
+    pub fn expired_at(&mut self, key: &[u8]) -> Option<Option<u128>> {
+        self.lookup(key).map(|store_value| store_value.expired_at)
+    }
+
+    /// Changes the expiry of an existing key, `None` making it persistent.
+    /// Returns whether the key existed.
+    pub fn set_expired_at(&mut self, key: &[u8], expired_at: Option<u128>) -> bool {
+        match self.lookup_mut(key) {
+            Some(store_value) => store_value.expired_at = expired_at,
+            None => return false,
+        }
+        match expired_at {
+            Some(_) if !self.volatile.contains(key) => {
+                self.volatile.insert(Bytes::copy_from_slice(key))
+            }
+            Some(_) => {}
+            None => self.volatile.remove(key),
+        }
+        true
+    }
+
+    /// The keys that have not expired, in no particular order.
+    pub fn keys(&self) -> Vec<Bytes> {
+        let now = self.clock.now();
+        self.map
+            .iter()
+            .filter(|(_, store_value)| !store_value.is_expired(now))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Continues a SCAN from `cursor`, visiting buckets until about `count`
+    /// keys have been found or ten times `count` buckets were empty. Returns
+    /// the next cursor, 0 when done, and the keys that have not expired.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<Bytes>) {
+        let now = self.clock.now();
+        let mut keys = vec![];
+        let mut cursor = cursor;
+        let mut max_iterations = count.saturating_mul(10);
+        loop {
+            cursor = self.map.scan(cursor, |key, store_value| {
+                if !store_value.is_expired(now) {
+                    keys.push(key.clone());
+                }
+            });
+            max_iterations -= 1;
+            if cursor == 0 || max_iterations == 0 || keys.len() >= count {
+                break;
+            }
+        }
+        (cursor, keys)
+    }
+
+    /// Deletes expired keys found in a random sample of the keys with a TTL,
+    /// sampling again while more than a tenth of the sample had expired, like
+    /// Redis's `activeExpireCycle`. Gives up once `time_limit` has passed and
+    /// returns how many keys were deleted.
+    pub fn active_expire_cycle(&mut self, time_limit: Duration) -> u64 {
+        let started = Instant::now();
+        let mut deleted = 0;
+        loop {
+            let sampled = ACTIVE_EXPIRE_KEYS_PER_LOOP.min(self.volatile.len());
+            if sampled == 0 {
+                break;
+            }
+            let now = self.clock.now();
+            let mut expired = 0;
+            for _ in 0..sampled {
+                if self.volatile.is_empty() {
+                    break;
+                }
+                let index = self.rng.next() as usize % self.volatile.len();
+                let key = self.volatile.get(index).clone();
+                if self
+                    .map
+                    .get(&key)
+                    .is_some_and(|store_value| store_value.is_expired(now))
+                {
+                    self.remove_value(&key);
+                    expired += 1;
+                }
+            }
+            deleted += expired as u64;
+            if expired * 100 <= sampled * ACTIVE_EXPIRE_ACCEPTABLE_STALE
+                || started.elapsed() >= time_limit
+            {
+                break;
             }
-            None => None,
         }
+        self.stats
+            .expired_keys
+            .fetch_add(deleted, Ordering::Relaxed);
+        deleted
+    }
+
+    /// Inserts into the map, keeping `volatile` in step with the TTL.
+    fn insert_value(&mut self, key: Bytes, store_value: StoreValue) {
+        if store_value.expired_at.is_some() {
+            self.volatile.insert(key.clone());
+        } else {
+            self.volatile.remove(&key);
+        }
+        self.map.insert(key, store_value);
     }
+
+    fn remove_value(&mut self, key: &[u8]) -> Option<StoreValue> {
+        self.volatile.remove(key);
+        self.map.remove(key)
+    }
+}
+
+/// The keys that have a TTL, in a vector so they can be sampled at random.
+#[derive(Debug, Clone, PartialEq, Default)]
+struct VolatileKeys {
+    keys: Vec<Bytes>,
+    positions: HashMap<Bytes, usize>,
+}
+
+impl VolatileKeys {
+    fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        self.positions.contains_key(key)
+    }
+
+    fn get(&self, index: usize) -> &Bytes {
+        &self.keys[index]
+    }
+
+    fn insert(&mut self, key: Bytes) {
+        if !self.positions.contains_key(&key) {
+            self.positions.insert(key.clone(), self.keys.len());
+            self.keys.push(key);
+        }
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        if let Some(position) = self.positions.remove(key) {
+            self.keys.swap_remove(position);
+            if let Some(moved) = self.keys.get(position) {
+                self.positions.insert(moved.clone(), position);
+            }
+        }
+    }
+}
+
+/// xorshift64, good enough to pick keys to sample.
+#[derive(Debug, Clone, PartialEq)]
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // the state must never be zero
+        XorShift(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+}
+
+/// What a `Store::set` did, and the value the key held before it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetOutcome {
+    pub written: bool,
+    pub previous: Option<Bytes>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum SetCondition {
+    #[default]
+    Always,
+    /// `NX`: only set a key that does not exist.
+    IfNotExists,
+    /// `XX`: only set a key that already exists.
+    IfExists,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Expiration {
+    /// Expires at this Unix time in milliseconds.
+    At(u128),
+    /// `KEEPTTL`: keeps the TTL of the value being replaced.
+    KeepTtl,
+}
+
+#[derive(Default)]
 pub struct SetOptions {
-    expire: Option<u64>,
+    expiration: Option<Expiration>,
+    condition: SetCondition,
 }
 
 impl SetOptions {
     pub fn new() -> Self {
-        SetOptions { expire: None }
+        SetOptions::default()
+    }
+
+    pub fn set_expiration(&mut self, expiration: Expiration) -> &Self {
+        self.expiration = Some(expiration);
+        self
     }
 
-    pub fn set_expire(&mut self, expire: u64) -> &Self {
-        self.expire = Some(expire);
+    pub fn set_condition(&mut self, condition: SetCondition) -> &Self {
+        self.condition = condition;
         self
     }
 }
 
-fn get_unixtime() -> Duration {
-    SystemTime::now().duration_since(UNIX_EPOCH).unwrap()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn expiring_at(expired_at: u128) -> SetOptions {
+        let mut options = SetOptions::new();
+        options.set_expiration(Expiration::At(expired_at));
+        options
+    }
+
+    #[test]
+    fn it_should_delete_an_expired_key_on_access() {
+        let mut store = Store::new();
+        store.set(Bytes::from("key"), Bytes::from("value"), &expiring_at(1));
+
+        assert_eq!(store.get(b"key"), None);
+        assert!(store.map.is_empty());
+        assert_eq!(store.volatile.len(), 0);
+        assert_eq!(store.stats.expired_keys.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn it_should_delete_expired_keys_in_the_active_expire_cycle() {
+        let mut store = Store::new();
+        for i in 0..100 {
+            store.set(
+                Bytes::from(format!("expired:{}", i)),
+                Bytes::new(),
+                &expiring_at(1),
+            );
+        }
+        for i in 0..10 {
+            store.set(
+                Bytes::from(format!("key:{}", i)),
+                Bytes::new(),
+                &SetOptions::new(),
+            );
+        }
+
+        assert_eq!(store.active_expire_cycle(Duration::from_secs(10)), 100);
+        assert_eq!(store.map.len(), 10);
+        assert_eq!(store.volatile.len(), 0);
+        assert_eq!(store.stats.expired_keys.load(Ordering::Relaxed), 100);
+    }
+
+    #[test]
+    fn it_should_change_a_value_in_place() {
+        let mut store = Store::with_clock(Arc::new(ManualClock::new(1_000)));
+        store.set(Bytes::from("key"), Bytes::from("abc"), &expiring_at(61_000));
+
+        store
+            .get_or_insert_mut(&Bytes::from("key"))
+            .extend_from_slice(b"def");
+        assert_eq!(store.get(b"key"), Some(Bytes::from("abcdef")));
+        assert_eq!(store.expired_at(b"key"), Some(Some(61_000)));
+
+        store.get_or_insert_mut(&Bytes::from("new")).push(b'x');
+        assert_eq!(store.get(b"new"), Some(Bytes::from("x")));
+        assert_eq!(store.expired_at(b"new"), Some(None));
+        assert_eq!(store.get_mut(b"missing"), None);
+    }
+
+    #[test]
+    fn it_should_track_keys_with_a_ttl() {
+        let mut store = Store::with_clock(Arc::new(ManualClock::new(1_000)));
+        let future = 61_000;
+        store.set(Bytes::from("a"), Bytes::new(), &expiring_at(future));
+        store.set(Bytes::from("b"), Bytes::new(), &expiring_at(future));
+        assert_eq!(store.volatile.len(), 2);
+
+        store.rename(b"a", Bytes::from("c"));
+        store.set_expired_at(b"b", None);
+        assert_eq!(store.volatile.keys, vec![Bytes::from("c")]);
+        assert_eq!(store.expired_at(b"b"), Some(None));
+
+        store.set_expired_at(b"b", Some(future));
+        store.set_expired_at(b"c", Some(future + 1));
+        assert_eq!(store.volatile.len(), 2);
+        assert_eq!(store.expired_at(b"c"), Some(Some(future + 1)));
+        store.set_expired_at(b"b", None);
+
+        store.set(Bytes::from("c"), Bytes::new(), &SetOptions::new());
+        assert_eq!(store.volatile.len(), 0);
+        assert_eq!(store.active_expire_cycle(Duration::from_secs(10)), 0);
+    }
 }